@@ -1,10 +1,16 @@
+use std::path::PathBuf;
+
 use clap::{
     arg,
-    builder::{Command, NonEmptyStringValueParser, TypedValueParser},
+    builder::{
+        BoolishValueParser, Command, NonEmptyStringValueParser, PossibleValuesParser,
+        TypedValueParser,
+    },
     error::{ContextKind, ContextValue, Error, ErrorKind, RichFormatter},
     Arg, ArgAction, ArgMatches, ColorChoice, FromArgMatches, Parser, Subcommand, ValueEnum,
     ValueHint,
 };
+use fimfic_tracker::{DownloadFormat, SensibilityLevel};
 
 #[derive(Parser, Debug, PartialEq)]
 #[clap(name = "fimfic-tracker", version, author)]
@@ -26,6 +32,19 @@ pub struct Args {
     /// When to use colors.
     #[clap(long, display_order = 3, value_enum, default_value_t)]
     pub color: ColorChoice,
+    /// Wait for another instance to release the tracker file instead of failing, indefinitely or
+    /// up to the given amount of seconds.
+    #[clap(
+        long,
+        value_name = "SECONDS",
+        display_order = 4,
+        num_args(0..=1),
+        require_equals(true)
+    )]
+    pub wait_lock: Option<Option<u64>>,
+    /// Don't make any request, using the responses kept in the cache instead.
+    #[clap(long, display_order = 5)]
+    pub offline: bool,
     #[clap(subcommand)]
     pub subcommand: SubCommand,
 }
@@ -39,7 +58,25 @@ pub enum SubCommand {
     #[clap(display_order = 3)]
     List(List),
     #[clap(display_order = 4)]
+    Info(Info),
+    #[clap(display_order = 5)]
     Download(Download),
+    #[clap(display_order = 6)]
+    Check(Check),
+    #[clap(display_order = 7)]
+    Set(Set),
+    #[clap(display_order = 8)]
+    Tag(Tag),
+    #[clap(display_order = 9)]
+    Note(Note),
+    #[clap(display_order = 10)]
+    Versions(Versions),
+    #[clap(display_order = 11)]
+    History(History),
+    #[clap(display_order = 12)]
+    Cache(Cache),
+    #[clap(display_order = 13)]
+    Migrate(Migrate),
 }
 
 #[derive(Clone)]
@@ -151,6 +188,30 @@ pub struct Untrack {
     pub ids: Vec<u32>,
 }
 
+/// Checks that `tag` isn't empty and has neither whitespace nor commas, which separate the tags
+/// in `$TAGS`.
+fn parse_tag(tag: &str) -> Result<String, String> {
+    if tag.is_empty() {
+        Err("a tag can't be empty".into())
+    } else if tag.contains(|c: char| c == ',' || c.is_whitespace()) {
+        Err("a tag can't have whitespace or commas".into())
+    } else {
+        Ok(tag.to_string())
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum ListFormat {
+    /// A JSON array of stories.
+    Json,
+    /// A JSON object per line.
+    Jsonl,
+    /// Comma-separated values, with a header row.
+    Csv,
+    /// Tab-separated values, with a header row.
+    Tsv,
+}
+
 #[derive(ValueEnum, Debug, Clone, PartialEq)]
 pub enum SortKey {
     Id,
@@ -159,6 +220,9 @@ pub enum SortKey {
     Chapters,
     Words,
     Update,
+    Added,
+    Checked,
+    Downloaded,
 }
 
 #[derive(Debug, PartialEq)]
@@ -271,18 +335,43 @@ impl FromArgMatches for StatusFilter {
 /// List all stories that are being tracked.
 pub struct List {
     /// Show only the ID and title of each tracked story.
-    #[clap(short, long, display_order = 1)]
+    #[clap(short, long, display_order = 1, conflicts_with = "format")]
     pub short: bool,
+    /// Output the stories in a machine-readable format instead.
+    #[clap(long, value_name = "FORMAT", display_order = 4, value_enum)]
+    pub format: Option<ListFormat>,
     /// Sort stories by the given key.
     #[clap(long, value_name = "KEY", display_order = 2, value_enum)]
     pub sort_by: Option<SortKey>,
     /// Reverse the order of the list.
     #[clap(short, long, display_order = 3)]
     pub reverse: bool,
+    /// Show only the stories with the given tag, or with all of them if given more than once.
+    #[clap(
+        short,
+        long,
+        value_name = "TAG",
+        display_order = 5,
+        value_parser(parse_tag)
+    )]
+    pub tag: Vec<String>,
     #[clap(flatten)]
     pub status_filter: StatusFilter,
 }
 
+#[derive(clap::Args, Debug, PartialEq)]
+#[clap(visible_alias = "i")]
+/// Shows everything known of a tracked story, without requesting anything.
+pub struct Info {
+    /// ID or URL of the story.
+    #[clap(
+        value_name = "ID_OR_URL",
+        value_hint(ValueHint::Other),
+        value_parser(StoryValueParser)
+    )]
+    pub id: u32,
+}
+
 #[derive(Debug, PartialEq)]
 pub enum Prompt {
     AssumeYes,
@@ -332,6 +421,43 @@ pub struct Download {
     /// Download no matter the presence of updates.
     #[clap(short, long, display_order = 1)]
     pub force: bool,
+    /// Keep going with the rest of the stories when one fails, exiting with code 2 at the end if
+    /// some of them failed.
+    #[clap(short, long, display_order = 2)]
+    pub keep_going: bool,
+    /// Write a JSON report of what happened to each story into the given file.
+    #[clap(
+        long,
+        value_name = "FILE",
+        display_order = 3,
+        value_hint(ValueHint::FilePath)
+    )]
+    pub report: Option<PathBuf>,
+    /// Print a JSON report of what happened to each story into stdout.
+    #[clap(long, display_order = 4)]
+    pub json: bool,
+    #[clap(flatten)]
+    pub prompt: Prompt,
+    /// IDs or URLs of stories to check.
+    #[clap(
+        value_name = "ID_OR_URL",
+        value_hint(ValueHint::Other),
+        value_parser(StoryValueParser)
+    )]
+    pub ids: Vec<u32>,
+}
+
+#[derive(clap::Args, Debug, PartialEq)]
+/// Checks for updates on tracking list without downloading them.
+pub struct Check {
+    /// Save the current data of the stories with changes into the tracking list, so they aren't
+    /// reported (nor downloaded) again.
+    #[clap(short, long, display_order = 1)]
+    pub save: bool,
+    /// Keep going with the rest of the stories when one fails, exiting with code 2 at the end if
+    /// some of them failed.
+    #[clap(short, long, display_order = 2)]
+    pub keep_going: bool,
     #[clap(flatten)]
     pub prompt: Prompt,
     /// IDs or URLs of stories to check.
@@ -343,6 +469,189 @@ pub struct Download {
     pub ids: Vec<u32>,
 }
 
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum OverrideKey {
+    DownloadFormat,
+    Exec,
+    SensibilityLevel,
+    DownloadDir,
+    SkipDownload,
+}
+
+#[derive(clap::Args, Debug, PartialEq)]
+#[clap(visible_alias = "edit")]
+/// Sets configuration values for specific stories, or shows them if none is given.
+pub struct Set {
+    /// Formats to download the stories in, separated by commas.
+    #[clap(
+        long,
+        value_name = "FORMATS",
+        display_order = 1,
+        value_delimiter = ',',
+        value_parser(
+            PossibleValuesParser::new(["html", "epub", "txt"])
+                .try_map(|format| format.parse::<DownloadFormat>())
+        )
+    )]
+    pub download_format: Option<Vec<DownloadFormat>>,
+    /// Command to execute instead of downloading the stories, where an empty one downloads them
+    /// from Fimfiction.
+    #[clap(
+        long,
+        value_name = "COMMAND",
+        display_order = 2,
+        allow_hyphen_values = true
+    )]
+    pub exec: Option<String>,
+    /// Sensibility level for the updates of the stories, from 0 (only chapters) to 2 (anything).
+    #[clap(
+        long,
+        value_name = "LEVEL",
+        display_order = 3,
+        value_parser(
+            PossibleValuesParser::new(["0", "1", "2"])
+                .try_map(|level| level.parse::<SensibilityLevel>())
+        )
+    )]
+    pub sensibility_level: Option<SensibilityLevel>,
+    /// Directory to download the stories into.
+    #[clap(
+        long,
+        value_name = "DIR",
+        display_order = 4,
+        value_hint(ValueHint::DirPath)
+    )]
+    pub download_dir: Option<String>,
+    /// Whether to only keep track of the updates of the stories, without downloading them.
+    #[clap(
+        long,
+        value_name = "BOOL",
+        display_order = 5,
+        value_parser(BoolishValueParser::new())
+    )]
+    pub skip_download: Option<bool>,
+    /// Options to go back to their configured value, separated by commas.
+    #[clap(
+        long,
+        value_name = "OPTIONS",
+        display_order = 6,
+        value_enum,
+        value_delimiter = ','
+    )]
+    pub unset: Vec<OverrideKey>,
+    /// IDs or URLs of stories to set the values of.
+    #[clap(
+        value_name = "ID_OR_URL",
+        required = true,
+        value_hint(ValueHint::Other),
+        value_parser(StoryValueParser)
+    )]
+    pub ids: Vec<u32>,
+}
+
+#[derive(clap::Args, Debug, PartialEq)]
+/// Adds or removes tags of a story.
+pub struct Tag {
+    #[clap(subcommand)]
+    pub action: TagAction,
+}
+
+#[derive(Subcommand, Debug, PartialEq)]
+pub enum TagAction {
+    /// Adds tags to a story.
+    #[clap(display_order = 1)]
+    Add {
+        /// ID or URL of the story.
+        #[clap(
+            value_name = "ID_OR_URL",
+            value_hint(ValueHint::Other),
+            value_parser(StoryValueParser)
+        )]
+        id: u32,
+        /// Tags to add, without whitespace or commas.
+        #[clap(value_name = "TAG", required = true, value_parser(parse_tag))]
+        tags: Vec<String>,
+    },
+    /// Removes tags from a story.
+    #[clap(display_order = 2)]
+    Remove {
+        /// ID or URL of the story.
+        #[clap(
+            value_name = "ID_OR_URL",
+            value_hint(ValueHint::Other),
+            value_parser(StoryValueParser)
+        )]
+        id: u32,
+        /// Tags to remove.
+        #[clap(value_name = "TAG", required = true, value_parser(parse_tag))]
+        tags: Vec<String>,
+    },
+}
+
+#[derive(clap::Args, Debug, PartialEq)]
+/// Shows, writes or removes the note of a story.
+pub struct Note {
+    /// Remove the note.
+    #[clap(short, long, display_order = 1, conflicts_with = "note")]
+    pub remove: bool,
+    /// ID or URL of the story.
+    #[clap(
+        value_name = "ID_OR_URL",
+        value_hint(ValueHint::Other),
+        value_parser(StoryValueParser)
+    )]
+    pub id: u32,
+    /// Text to write as the note, replacing the previous one.
+    #[clap(value_name = "TEXT", value_parser(NonEmptyStringValueParser::new()))]
+    pub note: Option<String>,
+}
+
+#[derive(clap::Args, Debug, PartialEq)]
+/// Lists the previous copies kept of a story.
+pub struct Versions {
+    /// ID or URL of the story.
+    #[clap(
+        value_name = "ID_OR_URL",
+        value_hint(ValueHint::Other),
+        value_parser(StoryValueParser)
+    )]
+    pub id: u32,
+}
+
+#[derive(clap::Args, Debug, PartialEq)]
+/// Shows the changes found on a story, or on every story if none is given, when checking for
+/// updates.
+pub struct History {
+    /// ID or URL of the story.
+    #[clap(
+        value_name = "ID_OR_URL",
+        value_hint(ValueHint::Other),
+        value_parser(StoryValueParser)
+    )]
+    pub id: Option<u32>,
+}
+
+#[derive(clap::Args, Debug, PartialEq)]
+/// Shows or removes the responses from Fimfiction kept in the cache.
+pub struct Cache {
+    #[clap(subcommand)]
+    pub action: CacheAction,
+}
+
+#[derive(Subcommand, Debug, PartialEq)]
+pub enum CacheAction {
+    /// Shows the amount of responses kept in the cache and their size.
+    #[clap(display_order = 1)]
+    Stats,
+    /// Removes every response kept in the cache.
+    #[clap(display_order = 2)]
+    Clear,
+}
+
+#[derive(clap::Args, Debug, PartialEq)]
+/// Rewrites the tracker file in the current version of its format.
+pub struct Migrate {}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -373,6 +682,129 @@ mod test {
         assert_id!([prefixes] "196256/1/the-moons-apprentice/original-oneshot-prelude-a-dream-fulfilled", 196256);
     }
 
+    #[test]
+    fn set_arguments() {
+        let args = Args::try_parse_from([
+            "fimfic-tracker",
+            "set",
+            "--download-format",
+            "epub,txt",
+            "--skip-download",
+            "yes",
+            "--unset",
+            "exec,download-dir",
+            "196256",
+        ])
+        .expect("arguments should be valid");
+
+        assert_eq!(
+            args.subcommand,
+            SubCommand::Set(Set {
+                download_format: Some(vec![DownloadFormat::EPUB, DownloadFormat::TXT]),
+                exec: None,
+                sensibility_level: None,
+                download_dir: None,
+                skip_download: Some(true),
+                unset: vec![OverrideKey::Exec, OverrideKey::DownloadDir],
+                ids: vec![196256],
+            })
+        );
+
+        assert!(
+            Args::try_parse_from(["fimfic-tracker", "set", "--sensibility-level", "3", "1"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn offline_info_arguments() {
+        let args = Args::try_parse_from([
+            "fimfic-tracker",
+            "--offline",
+            "info",
+            "https://www.fimfiction.net/story/196256/the-moons-apprentice",
+        ])
+        .expect("arguments should be valid");
+
+        assert!(args.offline);
+        assert_eq!(args.subcommand, SubCommand::Info(Info { id: 196256 }));
+    }
+
+    #[test]
+    fn tag_note_arguments() {
+        let args = Args::try_parse_from([
+            "fimfic-tracker",
+            "tag",
+            "add",
+            "196256",
+            "sci-fi",
+            "to-read",
+        ])
+        .expect("arguments should be valid");
+        assert_eq!(
+            args.subcommand,
+            SubCommand::Tag(Tag {
+                action: TagAction::Add {
+                    id: 196256,
+                    tags: vec!["sci-fi".into(), "to-read".into()]
+                }
+            })
+        );
+
+        assert!(Args::try_parse_from(["fimfic-tracker", "tag", "add", "196256"]).is_err());
+        assert!(Args::try_parse_from(["fimfic-tracker", "tag", "add", "196256", "a,b"]).is_err());
+        assert!(Args::try_parse_from(["fimfic-tracker", "list", "--tag", "to read"]).is_err());
+
+        let args =
+            Args::try_parse_from(["fimfic-tracker", "note", "196256", "Read after the prequel"])
+                .expect("arguments should be valid");
+        assert_eq!(
+            args.subcommand,
+            SubCommand::Note(Note {
+                remove: false,
+                id: 196256,
+                note: Some("Read after the prequel".into())
+            })
+        );
+
+        assert!(
+            Args::try_parse_from(["fimfic-tracker", "note", "--remove", "196256", "text"]).is_err()
+        );
+    }
+
+    #[test]
+    fn history_arguments() {
+        let args =
+            Args::try_parse_from(["fimfic-tracker", "history"]).expect("arguments should be valid");
+        assert_eq!(args.subcommand, SubCommand::History(History { id: None }));
+
+        let args = Args::try_parse_from([
+            "fimfic-tracker",
+            "history",
+            "https://www.fimfiction.net/story/196256/the-moons-apprentice",
+        ])
+        .expect("arguments should be valid");
+        assert_eq!(
+            args.subcommand,
+            SubCommand::History(History { id: Some(196256) })
+        );
+    }
+
+    #[test]
+    fn cache_arguments() {
+        let args = Args::try_parse_from(["fimfic-tracker", "cache", "clear"])
+            .expect("arguments should be valid");
+
+        assert_eq!(
+            args.subcommand,
+            SubCommand::Cache(Cache {
+                action: CacheAction::Clear
+            })
+        );
+
+        assert!(Args::try_parse_from(["fimfic-tracker", "cache"]).is_err());
+    }
+
     #[test]
     fn filter_all() {
         let filter = StatusFilter::all();
@@ -166,7 +166,12 @@ pub fn pretty_print(error: TrackerError) {
                 .insert("The story changed its ID somehow while checking for an update.".into());
             let _ = error_message.insert(ErrorMessage::Internal);
         }
-        ErrorKind::TrackerFormat { action, error, .. } => match action {
+        ErrorKind::TrackerFormat {
+            action,
+            error,
+            backup,
+            ..
+        } => match action {
             Action::Serializing => {
                 let _ = explanation
                     .insert("The cached story data couldn't be prepared to be saved.".into());
@@ -180,9 +185,9 @@ pub fn pretty_print(error: TrackerError) {
 
                 if error.is_data() {
                     fixes.push(
-                        "Did the tracker file come from the legacy version? \
-                        The storing format has changed and you have to migrate to be able to use it \
-                        with this version.",
+                        "Did you manually modify it? \
+                        Check that every story still has all of its fields and that their values \
+                        follow the storing format.",
                     );
                 }
 
@@ -197,6 +202,13 @@ pub fn pretty_print(error: TrackerError) {
                 }
 
                 if !fixes.is_empty() {
+                    if backup.is_some() {
+                        fixes.push(
+                            "The previous version of the tracker file is kept next to it with a \
+                            `.bak` suffix, but it couldn't be understood either. \
+                            If you have another copy, you can use it in place of the tracker file.",
+                        );
+                    }
                     fixes.push(
                         "If worst come to worst you can still save your list by \
                         (1) taking note of any story ID that you can from it, \
@@ -224,6 +236,42 @@ pub fn pretty_print(error: TrackerError) {
                 };
             }
         },
+        ErrorKind::UnsupportedTrackerVersion { .. } => {
+            let _ = explanation.insert(
+                "The tracker file was written by a more recent version of the application.".into(),
+            );
+            let _ = help.insert(
+                "Update the application to be able to use it, any older version cannot read it."
+                    .into(),
+            );
+            let _ = error_message.insert(ErrorMessage::Fixable);
+        }
+        ErrorKind::TrackerLocked { .. } => {
+            let _ = explanation.insert(
+                "Another instance of the application is using the tracker file, \
+                saving from this one could have overwritten its changes."
+                    .into(),
+            );
+            let _ = help.insert(
+                "Wait for the other instance to finish or use `--wait-lock` to wait for it to \
+                release the tracker file."
+                    .into(),
+            );
+            let _ = error_message.insert(ErrorMessage::Fixable);
+        }
+        ErrorKind::Offline { .. } => {
+            let _ = explanation.insert(
+                "The command needed something from Fimfiction that wasn't kept in the cache, \
+                or to execute a download command, but neither is done while offline."
+                    .into(),
+            );
+            let _ = help.insert(
+                "Run it again without `--offline` once you are connected, or with `offline = false` \
+                if it's enabled in the configuration."
+                    .into(),
+            );
+            let _ = error_message.insert(ErrorMessage::Fixable);
+        }
         // Io: Not much that can say about it.
         // Request: Could be nice to have messages in the style of a web browser, but I don't know
         //   how to determine each case or what even a good message would be.
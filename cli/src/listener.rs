@@ -1,8 +1,68 @@
-use console::{style, Term};
-use fimfic_tracker::{downloader::ProgressListener, Config, Story};
+use std::path::Path;
+
+use console::{measure_text_width, style, Term};
+use fimfic_tracker::{
+    downloader::{DownloadProgress, ProgressListener},
+    Config, DownloadFormat, Story,
+};
 
 use crate::logger::PROGRESS_PREFIX;
-use crate::readable::ReadableBytes;
+use crate::readable::{ReadableBytes, ReadableDuration};
+
+/// Width of the bar shown when the size of a download is known, without the brackets.
+const BAR_WIDTH: usize = 20;
+
+/// The bar, percentage, size, speed and ETA of `progress`, as much of it as it is known.
+fn progress_suffix(progress: &DownloadProgress) -> String {
+    let speed = progress
+        .speed()
+        .map(|speed| format!(", {}/s", ReadableBytes(speed as usize)))
+        .unwrap_or_default();
+
+    match (progress.total, progress.percentage()) {
+        (Some(total), Some(percentage)) => {
+            let filled = (percentage / 100.0 * BAR_WIDTH as f64) as usize;
+            let bar = if filled < BAR_WIDTH {
+                format!(
+                    "{}>{}",
+                    "=".repeat(filled),
+                    " ".repeat(BAR_WIDTH - filled - 1)
+                )
+            } else {
+                "=".repeat(BAR_WIDTH)
+            };
+            let eta = progress
+                .eta()
+                .map(|eta| format!(", ETA {}", ReadableDuration(eta)))
+                .unwrap_or_default();
+
+            format!(
+                " [{}] {:>3.0}% {}/{}{}{}",
+                bar,
+                percentage,
+                ReadableBytes(progress.bytes),
+                ReadableBytes(total),
+                speed,
+                eta
+            )
+        }
+        _ => format!(" [{}{}]", ReadableBytes(progress.bytes), speed),
+    }
+}
+
+/// The end of `text` that fits into `cols` columns, cut on a character boundary.
+fn fit_end(text: &str, cols: usize) -> &str {
+    let mut width = 0;
+    for (idx, c) in text.char_indices().rev() {
+        let end = idx + c.len_utf8();
+        width += measure_text_width(&text[idx..end]);
+        if width > cols {
+            return &text[end..];
+        }
+    }
+
+    text
+}
 
 #[derive(Clone)]
 pub struct ProgressOutput {
@@ -20,8 +80,9 @@ impl ProgressOutput {
 }
 
 impl ProgressListener for ProgressOutput {
-    fn download_progress(&self, bytes: usize, filepath: &str) {
-        let started = bytes != 0;
+    fn download_progress(&self, progress: &DownloadProgress) {
+        let started = progress.is_started();
+        let filepath = progress.filepath;
 
         if !verbose_disabled!() {
             if !started {
@@ -40,17 +101,11 @@ impl ProgressListener for ProgressOutput {
             clear_last_lines!();
         };
 
-        let suffix = format!(" [{}]", ReadableBytes(bytes));
+        let suffix = progress_suffix(progress);
         let used_cols = PROGRESS_PREFIX.len() + suffix.len();
 
         if cols > used_cols {
-            let remaining = cols - used_cols;
-            let center = if remaining >= filepath.len() {
-                filepath
-            } else {
-                let idx = filepath.len() - remaining;
-                &filepath[idx..]
-            };
+            let center = fit_end(filepath, cols - used_cols);
 
             progress!("{}{}", center, style(suffix).green());
         } else {
@@ -58,9 +113,37 @@ impl ProgressListener for ProgressOutput {
         };
     }
 
-    fn successfull_client_download(&self, story: &Story) {
+    fn successfull_format_download(&self, _story: &Story, format: DownloadFormat, filepath: &Path) {
+        clear_last_lines!();
+
+        info!(
+            "{}{} {}",
+            PROGRESS_PREFIX,
+            style(format!(
+                "Downloaded {} into",
+                format.to_string().to_uppercase()
+            ))
+            .green(),
+            style(filepath.display()).green().bold()
+        );
+    }
+
+    fn unchanged_format_download(&self, _story: &Story, format: DownloadFormat, filepath: &Path) {
         clear_last_lines!();
 
+        info!(
+            "{}{} {}",
+            PROGRESS_PREFIX,
+            style(format!(
+                "Unchanged {}, kept",
+                format.to_string().to_uppercase()
+            ))
+            .green(),
+            style(filepath.display()).green().bold()
+        );
+    }
+
+    fn successfull_client_download(&self, story: &Story) {
         info!(
             "{} {} {}",
             style("Successfully downloaded").green(),
@@ -93,3 +176,17 @@ impl ProgressListener for ProgressOutput {
         );
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fit_end_on_char_boundaries() {
+        assert_eq!(fit_end("A Title.html", 20), "A Title.html");
+        assert_eq!(fit_end("A Title.html", 9), "itle.html");
+        assert_eq!(fit_end("Ñandú Ñandú.html", 7), "dú.html");
+        assert_eq!(fit_end("Ñandú Ñandú.html", 10), "Ñandú.html");
+        assert_eq!(fit_end("Ñandú Ñandú.html", 0), "");
+    }
+}
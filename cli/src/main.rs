@@ -1,12 +1,13 @@
 #[macro_use]
 extern crate log;
 
-use std::path::Path;
+use std::{path::Path, time::Duration};
 
 use clap::Parser;
 
 use fimfic_tracker::{
-    downloader::BlockingRequester, Config, ConfigBuilder, Result, StoryData, TrackerError,
+    downloader::BlockingRequester, errors::ErrorKind, Config, ConfigBuilder, Result, StoryData,
+    TrackerError,
 };
 
 #[macro_use]
@@ -18,7 +19,7 @@ mod logger;
 mod readable;
 mod subcommands;
 
-use args::{Args, SubCommand};
+use args::{Args, Check, List, Note, SubCommand};
 use listener::ProgressOutput;
 
 pub type Requester = BlockingRequester<ProgressOutput>;
@@ -42,7 +43,7 @@ mod backup {
     use std::{io, path::Path};
 
     use chrono::Local;
-    use fimfic_tracker::{default_user_tracker_file, errors::ErrorKind};
+    use fimfic_tracker::default_user_tracker_file;
 
     fn is_permission_error(error: &TrackerError) -> bool {
         if let ErrorKind::Io(io_error) = &error.kind {
@@ -95,18 +96,42 @@ mod backup {
     }
 }
 
-fn run(args: Args) -> Result<()> {
+/// Whether `subcommand` can change the tracker data, so that it needs the exclusive lock on the
+/// tracker file and to be saved afterwards.
+fn changes_story_data(subcommand: &SubCommand) -> bool {
+    match subcommand {
+        SubCommand::Track(_)
+        | SubCommand::Untrack(_)
+        | SubCommand::Download(_)
+        | SubCommand::Set(_)
+        | SubCommand::Tag(_)
+        | SubCommand::Migrate(_) => true,
+        SubCommand::Check(Check { save, .. }) => *save,
+        SubCommand::Note(Note { remove, note, .. }) => *remove || note.is_some(),
+        SubCommand::List(_)
+        | SubCommand::Info(_)
+        | SubCommand::Versions(_)
+        | SubCommand::History(_)
+        | SubCommand::Cache(_) => false,
+    }
+}
+
+/// Runs the application, returning the code to exit with.
+fn run(args: Args) -> Result<i32> {
     debug!("Parsed arguments: {:?}", &args);
 
-    let config: Config = ConfigBuilder::from_default_sources()
-        .and_then(|builder| match args.config.as_ref() {
+    let mut builder =
+        ConfigBuilder::from_default_sources().and_then(|builder| match args.config.as_ref() {
             Some(path) => ConfigBuilder::from_file(path).map(|c| builder.merge(c)),
             None => Ok(builder),
-        })?
-        .into();
+        })?;
+    if args.offline {
+        builder = builder.offline(true);
+    }
+    let config: Config = builder.into();
     debug!("Loaded config: {:?}", &config);
 
-    let requester = BlockingRequester::new(config.clone(), ProgressOutput::new(config.clone()));
+    let requester = BlockingRequester::new(config.clone(), ProgressOutput::new(config.clone()))?;
 
     for path in [
         Some(config.download_dir.as_ref()),
@@ -120,15 +145,52 @@ fn run(args: Args) -> Result<()> {
         create_dir_all(path)?;
     }
 
+    // The commands that only read the tracker data can run along with each other.
+    let changes = changes_story_data(&args.subcommand);
     let mut story_data = StoryData::new(&config.tracker_file);
+    let locked = if changes {
+        story_data.lock()
+    } else {
+        story_data.lock_shared()
+    };
+    if let Err(err) = locked {
+        let timeout = match args.wait_lock {
+            Some(timeout) if matches!(err.kind, ErrorKind::TrackerLocked { .. }) => timeout,
+            _ => return Err(err),
+        };
+
+        info!("Waiting for another instance to release the tracker file ...");
+        let timeout = timeout.map(Duration::from_secs);
+        if changes {
+            story_data.lock_timeout(timeout)?;
+        } else {
+            story_data.lock_shared_timeout(timeout)?;
+        }
+    }
     story_data.load()?;
     debug!("Loaded story data: {:?}", &story_data);
+    if !story_data.migrations().is_empty() {
+        debug!("Migrated story data: {:?}", story_data.migrations());
+    }
 
+    // Migrating only needs to save when there was something to migrate.
+    let save = changes
+        && !(matches!(args.subcommand, SubCommand::Migrate(_))
+            && story_data.migrations().is_empty());
+    let mut exit_code = 0;
     let result = match args.subcommand {
         SubCommand::Track(track_args) => {
             subcommands::track(&config, &requester, &mut story_data, track_args)
         }
-        SubCommand::Untrack(_) | SubCommand::List(_) | SubCommand::Download(_)
+        SubCommand::Untrack(_)
+        | SubCommand::List(List { format: None, .. })
+        | SubCommand::Info(_)
+        | SubCommand::Download(_)
+        | SubCommand::Check(_)
+        | SubCommand::Set(_)
+        | SubCommand::Tag(_)
+        | SubCommand::Note(_)
+        | SubCommand::Versions(_)
             if story_data.is_empty() =>
         {
             warn!("There are no stories in the tracking list!");
@@ -139,14 +201,48 @@ fn run(args: Args) -> Result<()> {
             Ok(())
         }
         SubCommand::List(list_args) => {
-            subcommands::list(&story_data, list_args);
+            subcommands::list(&config, &story_data, list_args);
             Ok(())
         }
+        SubCommand::Info(info_args) => subcommands::info(&config, &story_data, info_args),
         SubCommand::Download(download_args) => {
             subcommands::download(&config, &requester, &mut story_data, download_args)
+                .map(|outcome| exit_code = outcome.exit_code())
+        }
+        SubCommand::Check(check_args) => {
+            subcommands::check(&config, &requester, &mut story_data, check_args)
+                .map(|outcome| exit_code = outcome.exit_code())
+        }
+        SubCommand::Set(set_args) => {
+            subcommands::set(&mut story_data, set_args);
+            Ok(())
+        }
+        SubCommand::Tag(tag_args) => {
+            subcommands::tag(&mut story_data, tag_args);
+            Ok(())
+        }
+        SubCommand::Note(note_args) => {
+            subcommands::note(&mut story_data, note_args);
+            Ok(())
+        }
+        SubCommand::Versions(versions_args) => {
+            subcommands::versions(&config, &story_data, versions_args)
+        }
+        SubCommand::History(history_args) => {
+            subcommands::history(&config, &story_data, history_args)
+        }
+        SubCommand::Cache(cache_args) => subcommands::cache(&config, cache_args),
+        SubCommand::Migrate(_) => {
+            subcommands::migrate(&story_data);
+            Ok(())
         }
     };
 
+    if !save {
+        debug!("Nothing to save to tracker file");
+        return result.map(|_| exit_code);
+    }
+
     match story_data.save() {
         Ok(_) => {
             debug!("Saved story data to tracker file");
@@ -166,15 +262,19 @@ fn run(args: Args) -> Result<()> {
         }
     };
 
-    result
+    result.map(|_| exit_code)
 }
 
 fn main() {
     let args = Args::parse();
     logger::configure(args.verbose, args.color);
 
-    if let Err(err) = run(args) {
-        error::pretty_print(err);
-        std::process::exit(1)
+    match run(args) {
+        Ok(0) => {}
+        Ok(code) => std::process::exit(code),
+        Err(err) => {
+            error::pretty_print(err);
+            std::process::exit(1)
+        }
     }
 }
@@ -1,4 +1,5 @@
 use std::fmt;
+use std::time::Duration;
 
 use chrono::{DateTime, Local, TimeZone, Utc};
 use chrono_humanize::{Accuracy, HumanTime, Tense};
@@ -15,6 +16,19 @@ impl fmt::Display for ReadableBytes {
     }
 }
 
+pub struct ReadableDuration(pub Duration);
+
+impl fmt::Display for ReadableDuration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let seconds = self.0.as_secs();
+        match (seconds / 3600, seconds / 60 % 60, seconds % 60) {
+            (0, 0, seconds) => write!(f, "{}s", seconds),
+            (0, minutes, seconds) => write!(f, "{}m {:02}s", minutes, seconds),
+            (hours, minutes, _) => write!(f, "{}h {:02}m", hours, minutes),
+        }
+    }
+}
+
 pub struct ReadableDate(pub DateTime<Utc>);
 
 impl fmt::Display for ReadableDate {
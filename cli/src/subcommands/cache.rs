@@ -0,0 +1,50 @@
+use console::style;
+
+use fimfic_tracker::{cache, Config, Result};
+
+use crate::args::{Cache, CacheAction};
+use crate::readable::ReadableBytes;
+
+pub fn cache(config: &Config, Cache { action }: Cache) -> Result<()> {
+    let dir = cache::responses_dir(config);
+
+    match action {
+        CacheAction::Stats => {
+            let stats = cache::stats(config)?;
+
+            if stats.entries == 0 {
+                info!("There are no responses kept in the cache");
+            } else {
+                info!(
+                    "Responses kept in {}: {}, taking {}",
+                    style(dir.display()).green(),
+                    style(stats.entries).bold(),
+                    style(ReadableBytes(stats.size as usize)).blue()
+                );
+            }
+
+            if !config.cache {
+                info!(
+                    "{}",
+                    style("Enable the `cache` option to keep them when requesting").dim()
+                );
+            }
+        }
+        CacheAction::Clear => {
+            let stats = cache::clear(config)?;
+
+            if stats.entries == 0 {
+                info!("There are no responses kept in the cache");
+            } else {
+                info!(
+                    "Responses removed from {}: {}, freeing {}",
+                    style(dir.display()).green(),
+                    style(stats.entries).bold(),
+                    style(ReadableBytes(stats.size as usize)).blue()
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,54 @@
+use console::style;
+
+use fimfic_tracker::{Config, Result, StoryData};
+
+use super::updates::{check_updates, print_failures, Outcome};
+use crate::args::Check;
+use crate::Requester;
+
+pub fn check(
+    config: &Config,
+    requester: &Requester,
+    story_data: &mut StoryData,
+    Check {
+        save,
+        keep_going,
+        prompt,
+        ref ids,
+    }: Check,
+) -> Result<Outcome> {
+    let checked = check_updates(config, requester, story_data, ids, prompt, keep_going)?;
+    let report = &checked.report;
+
+    if checked.printed {
+        separate!();
+    }
+
+    match report.to_download().count() {
+        0 => info!("There are no updates to download"),
+        1 => info!("{} story has an update to download", style(1).blue().bold()),
+        amount => info!(
+            "{} stories have an update to download",
+            style(amount).blue().bold()
+        ),
+    };
+
+    if save {
+        debug!(
+            "Saving changes: {:?}",
+            report
+                .changes()
+                .map(|check| &check.after)
+                .collect::<Vec<_>>()
+        );
+        report.apply_changes(story_data);
+        report.apply_checked_at(story_data, checked.checked_at);
+    }
+
+    let outcome = checked.outcome(story_data);
+    if outcome != Outcome::Success {
+        print_failures(report, "checking");
+    }
+
+    Ok(outcome)
+}
@@ -1,272 +1,62 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 
+use chrono::Utc;
 use console::style;
-use dialoguer::Confirm;
 
 use fimfic_tracker::{
-    Config, Id, Result, SensibilityLevel, Story, StoryData, StoryStatus, StoryUpdate, TrackerError,
+    checker::{FailureStage, StoryDownload, StoryFailure},
+    downloader::Downloaded,
+    Config, Id, Result, StoryData,
 };
 
-use crate::args::{Download, Prompt};
-use crate::readable::ReadableDate;
+use super::report::Report;
+use super::updates::{check_updates, print_failures, Outcome};
+use crate::args::Download;
 use crate::Requester;
 
-macro_rules! format_update {
-    (author, $before:expr => $after:expr) => {
-        format_update!([green] &$before, &$after)
-    };
-    (chapters, $before:expr => $after:expr) => {
-        format_update!([blue] $before, $after)
-    };
-    (words, $before:expr => $after:expr) => {
-        format_update!([blue] $before, $after)
-    };
-    (timestamp, $before:expr => $after:expr) => {
-        format_update!([yellow] ReadableDate($before), ReadableDate($after))
-    };
-    (status, $before:expr => $after:expr) => {
-        format_update!([yellow] $before, $after)
-    };
-    ([$color:ident] $before:expr, $after:expr) => {
-        format_args!(
-            "{} {} {}",
-            style($before).$color(),
-            style("=>").cyan(),
-            style($after).$color().bold()
-        )
-    };
-}
-
-macro_rules! info_story_checking {
-    ($story:expr) => {
-        info!("Checking for {} ...", format_story!($story));
-    };
-}
-
-macro_rules! info_update {
-    ([ignored] $story:expr, $on:ident, $before:expr => $after:expr) => {
-        info_update!($story, $on, $before, $after, ". Ignoring")
-    };
-    ($story:expr, $on:ident, $before:expr => $after:expr) => {
-        info_update!($story, $on, $before, $after, "")
-    };
-    ($story:expr, $on:ident, $before:expr, $after:expr, $extra:expr) => {
-        info!(
-            "{} has an update on {} ({}){}",
-            format_story!($story),
-            stringify!($on),
-            format_update!($on, $before => $after),
-            $extra
-        );
-    };
-}
-
-#[derive(Debug)]
-enum StoryDownload {
-    Update(Id, Story),
-    Forced(Id),
-}
-
 pub fn download(
     config: &Config,
     requester: &Requester,
     story_data: &mut StoryData,
     Download {
         force,
+        keep_going,
+        ref report,
+        json,
         prompt,
         ref ids,
     }: Download,
-) -> Result<()> {
-    let selected_ids: Vec<Id> = if ids.is_empty() {
-        story_data.keys().cloned().collect()
-    } else {
-        story_data
-            .keys()
-            .filter(|id| ids.contains(id))
-            .cloned()
-            .collect()
-    };
-    let mut ignored_ids: HashSet<Id> = HashSet::with_capacity(selected_ids.len());
-
-    let mut printed = false;
-
-    macro_rules! set_printed {
-        () => {
-            if !printed {
-                printed = true;
-            }
-        };
-    }
-
-    for (id, story) in story_data.iter().filter_map(|(id, story)| {
-        if selected_ids.contains(id) {
-            Some((*id, story))
-        } else {
-            None
-        }
-    }) {
-        if let StoryStatus::Incomplete = story.status {
-            continue;
-        }
-
-        set_printed!();
-
-        let status_notice = format!(
-            "{} has been marked as {} by the author",
-            format_story!(story),
-            format_status!(story)
-        );
-
-        match prompt {
-            Prompt::AssumeYes => {
-                info!("{}. Checking for an update on it anyways.", status_notice);
-            }
-            Prompt::AssumeNo => {
-                info!("{}. Skipping checking for an update on it.", status_notice);
-                ignored_ids.insert(id);
-            }
-            Prompt::Ask => {
-                let confirm = Confirm::new()
-                    .with_prompt(format!(
-                        "{}. Do you want to still check for an update on it?",
-                        status_notice
-                    ))
-                    .interact()
-                    .map_err(|err| {
-                        TrackerError::io(err)
-                            .context("failed to launch overwrite confirmation prompt")
-                    })?;
-
-                if !confirm {
-                    ignored_ids.insert(id);
-                }
-            }
-        }
-    }
-
-    if printed {
-        separate!();
-        printed = false;
-    }
-
-    let mut updated_stories: HashMap<Id, Story> = HashMap::with_capacity(selected_ids.len());
-    let mut ids_to_download: HashSet<Id> = HashSet::with_capacity(selected_ids.len());
-
-    for (id, story) in story_data
-        .iter()
-        .filter(|(id, _)| selected_ids.contains(id) && !ignored_ids.contains(id))
-        .map(|(id, story)| (*id, story))
-    {
-        info_story_checking!(story);
-        let updated_story: Story = requester.get_story_response(id)?.into();
-
-        let title_changed = story.title != updated_story.title;
-        let author_changed = story.author != updated_story.author;
-        let status_changed = story.status != updated_story.status;
-        let story_update = story.compare_to(&updated_story)?;
-
-        if story_update.is_some() || title_changed || author_changed || status_changed {
-            // If we are here, something will be printed to stderr. Be it by the specific cases
-            // just below or by the resulting StoryUpdate comparison.
-            set_printed!();
-
-            if title_changed || author_changed || status_changed {
-                clear_last_lines!();
-
-                if title_changed {
-                    info!(
-                        "{} has changed its title to {}",
-                        format_story!(story),
-                        style(&updated_story.title).green().bold()
-                    );
-                }
-
-                if author_changed {
-                    info!(
-                        "{} has changed its author ({})",
-                        format_story!(story),
-                        format_update!(author, story.author => updated_story.author)
-                    );
-                }
-
-                if status_changed {
-                    info!(
-                        "{} has changed its status ({})",
-                        format_story!(story),
-                        format_update!(status, story.status => updated_story.status),
-                    );
-                }
-
-                // Avoid this message from being repeated twice in verbose output.
-                if verbose_disabled!() {
-                    info_story_checking!(story);
-                }
-            }
-
-            updated_stories.insert(id, updated_story);
-        }
-
-        clear_last_lines!();
-
-        match story_update {
-            Some(StoryUpdate::Chapters { before, after }) => {
-                info_update!(story, chapters, before => after);
-            }
-            Some(StoryUpdate::Words { before, after })
-                if config.sensibility_level >= SensibilityLevel::IncludeWords =>
-            {
-                info_update!(story, words, before => after);
-            }
-            Some(StoryUpdate::DateTime { before, after })
-                if config.sensibility_level == SensibilityLevel::Anything =>
-            {
-                info_update!(story, timestamp, before => after);
-            }
-            Some(StoryUpdate::Words { before, after }) => {
-                info_update!([ignored] story, words, before => after);
-                continue;
-            }
-            Some(StoryUpdate::DateTime { before, after }) => {
-                info_update!([ignored] story, timestamp, before => after);
-                continue;
-            }
-            None => continue,
-        };
-
-        ids_to_download.insert(id);
-    }
+) -> Result<Outcome> {
+    let mut checked = check_updates(config, requester, story_data, ids, prompt, keep_going)?;
 
     // Update stories with ignored updates.
     // This way if the downloads fail, these should be saved by the "emergency save".
-    //
-    // After this block, `updated_stories` should only contain stories whose IDs are in
-    // `ids_to_download`.
-    {
-        let mut updated_ids = story_data
-            .keys()
-            .filter(|id| !ids_to_download.contains(id))
-            .filter_map(|id| updated_stories.remove_entry(id))
-            .collect::<Vec<(Id, Story)>>();
-
-        debug!("Ignored updates: {:?}", &updated_ids);
-
-        for (id, story) in updated_ids.drain(..) {
-            story_data.insert(id, story);
-        }
-    }
-
-    if printed {
+    debug!(
+        "Ignored updates: {:?}",
+        checked
+            .report
+            .changes()
+            .filter(|check| !check.should_download())
+            .map(|check| &check.after)
+            .collect::<Vec<_>>()
+    );
+    checked.report.apply_ignored(story_data);
+    checked
+        .report
+        .apply_checked_at(story_data, checked.checked_at);
+
+    if checked.printed {
         separate!();
     }
 
-    if !force && ids_to_download.is_empty() {
+    if !force && checked.report.to_download().next().is_none() {
         info!("There is nothing to download");
     } else if force {
         progress_or_info!(
             "{}",
             style(format!(
                 "Force downloading {}",
-                if ids.is_empty() && ignored_ids.is_empty() {
+                if ids.is_empty() && checked.skipped_ids.is_empty() {
                     "every story on the tracking list"
                 } else {
                     "selected stories"
@@ -281,23 +71,19 @@ pub fn download(
     let use_separator = config.exec.is_some() && !config.quiet;
     let delay = std::time::Duration::from_secs(config.download_delay);
 
-    let mut stories_to_download: Vec<StoryDownload> = story_data
-        .keys()
-        // Only download the stories that:
-        // (1) Whose IDs were given by the user if any.
-        // (2) The user responded to its prompt with Y.
-        .filter(|id| selected_ids.contains(id) && !ignored_ids.contains(id))
-        // Download all stories if the user forced it, otherwise only those who passed the update
-        // sensibility test.
-        .filter(|id| force || ids_to_download.contains(id))
-        .map(|id| match updated_stories.remove(id) {
-            Some(story) => StoryDownload::Update(*id, story),
-            None => StoryDownload::Forced(*id),
-        })
-        .collect();
+    // Only download the stories that were checked, which are the ones:
+    // (1) Whose IDs were given by the user if any.
+    // (2) The user responded to its prompt with Y.
+    //
+    // Download all of them if the user forced it, otherwise only those who passed the update
+    // sensibility test.
+    let mut stories_to_download = checked.report.downloads(force);
 
     debug!("Stories to download: {:?}", &stories_to_download);
 
+    let mut downloads: HashMap<Id, Downloaded> = HashMap::with_capacity(stories_to_download.len());
+    let mut aborted: Option<StoryFailure> = None;
+
     for (is_first, story_download) in stories_to_download
         .drain(..)
         .enumerate()
@@ -305,23 +91,66 @@ pub fn download(
     {
         download_delay!(!is_first, use_separator, delay);
 
-        match &story_download {
-            StoryDownload::Update(_, story) => requester.download(story)?,
-            // While this should be safe to unwrap, in the unlikely event that it panics the
-            // "emergency save" would be skipped.
-            // So I throw in a `match` to "safely" unwrap it and throw a warning if it is not
-            // present.
-            StoryDownload::Forced(id) => match story_data.get(id) {
-                Some(story) => requester.download(story)?,
-                None => warn!("{} is not present in the tracker file.", id),
-            },
+        let downloaded = match requester.download(story_download.story()) {
+            Ok(downloaded) => downloaded,
+            Err(err) if keep_going => {
+                // The tracked data is left as it was, so the update is found again on the next
+                // run.
+                let story = story_download.story();
+                warn!("Failed to download {}: {}", format_story!(story), err);
+                checked
+                    .report
+                    .push_failure(story, FailureStage::Download, err);
+                continue;
+            }
+            Err(err) => {
+                aborted = Some(StoryFailure {
+                    story: story_download.story().clone(),
+                    stage: FailureStage::Download,
+                    error: err,
+                });
+                break;
+            }
         };
 
         // Insert the update once it downloads.
-        if let StoryDownload::Update(id, story) = story_download {
-            story_data.insert(id, story);
+        let id = story_download.story().id;
+        if let StoryDownload::Update(story) = story_download {
+            story_data.insert(story.id, story);
+        }
+
+        if let Some(story) = story_data.get_mut(&id) {
+            // An update inserted above still has the check date from before this one.
+            story.last_checked_at = Some(checked.checked_at);
+            downloaded.record_download(story, Utc::now());
         }
+        downloads.insert(id, downloaded);
     }
 
-    Ok(())
+    let outcome = checked.outcome(story_data);
+    if aborted.is_none() && outcome != Outcome::Success {
+        print_failures(&checked.report, "downloading");
+    }
+
+    if report.is_some() || json {
+        let download_report = Report::new(story_data, &checked, &downloads, aborted.as_ref());
+
+        if let Some(path) = report {
+            match download_report.write(path) {
+                Ok(_) => debug!("Wrote download report to `{}`", path.display()),
+                // The error that stopped the run is the one to exit with.
+                Err(err) if aborted.is_some() => warn!("{}", err),
+                Err(err) => return Err(err),
+            }
+        }
+
+        if json {
+            println!("{}", download_report.to_json());
+        }
+    }
+
+    match aborted {
+        Some(failure) => Err(failure.error),
+        None => Ok(outcome),
+    }
 }
@@ -0,0 +1,130 @@
+use std::fmt::Display;
+
+use console::style;
+
+use fimfic_tracker::{
+    history::{self, HistoryEntry},
+    Config, Result, StoryData, StoryUpdate,
+};
+
+use crate::args::History;
+use crate::readable::ReadableDate;
+
+fn format_change<T: Display>(on: &str, before: T, after: T) -> String {
+    format!(
+        "{} {} {} {}",
+        on,
+        style(before).blue(),
+        style("=>").cyan(),
+        style(after).blue().bold()
+    )
+}
+
+/// The changes in `entry`, separated by commas.
+fn format_changes(entry: &HistoryEntry) -> String {
+    let mut changes = Vec::new();
+
+    match &entry.changes.update {
+        Some(StoryUpdate::Chapters { before, after }) => {
+            changes.push(format_change("chapters", before, after))
+        }
+        Some(StoryUpdate::Words { before, after }) => {
+            changes.push(format_change("words", before, after))
+        }
+        Some(StoryUpdate::DateTime { before, after }) => changes.push(format_change(
+            "timestamp",
+            ReadableDate(*before),
+            ReadableDate(*after),
+        )),
+        None => {}
+    }
+
+    if let Some(change) = &entry.changes.title_change {
+        changes.push(format_change("title", &change.before, &change.after));
+    }
+
+    if let Some(change) = &entry.changes.author_change {
+        changes.push(format_change("author", &change.before, &change.after));
+    }
+
+    if let Some(change) = &entry.changes.status_change {
+        changes.push(format_change("status", change.before, change.after));
+    }
+
+    changes.join(", ")
+}
+
+pub fn history(config: &Config, story_data: &StoryData, History { id }: History) -> Result<()> {
+    let mut entries = history::read(config)?;
+
+    let id = match id {
+        Some(id) => id,
+        None => {
+            if entries.is_empty() {
+                info!("There are no changes recorded in the history");
+                return Ok(());
+            }
+
+            info!("Changes found on the stories, from the oldest to the most recent:");
+
+            let id_width = entries
+                .iter()
+                .map(|entry| entry.id.to_string().len())
+                .max()
+                .unwrap_or_default();
+
+            for entry in entries {
+                println!(
+                    "{}  {:>width$}  {}: {}",
+                    style(ReadableDate(entry.detected_at)).yellow(),
+                    style(entry.id).blue(),
+                    style(&entry.title).green().bold(),
+                    format_changes(&entry),
+                    width = id_width
+                );
+            }
+
+            return Ok(());
+        }
+    };
+
+    entries.retain(|entry| entry.id == id);
+
+    match (story_data.get(&id), entries.last()) {
+        (None, None) => {
+            warn!(
+                "There is no story of ID {}{}",
+                style(id).bold(),
+                style(" on the tracking list.").yellow()
+            );
+            return Ok(());
+        }
+        (Some(story), None) => {
+            info!("There are no changes recorded for {}", format_story!(story));
+            return Ok(());
+        }
+        (Some(story), Some(_)) => {
+            info!(
+                "Changes found on {}, from the oldest to the most recent:",
+                format_story!(story)
+            );
+        }
+        // No longer tracked, but its changes are still in the history.
+        (None, Some(last)) => {
+            info!(
+                "Changes found on {}, from the oldest to the most recent:",
+                format_story!(last)
+            );
+        }
+    }
+
+    for entry in entries {
+        println!(
+            "{}  {}",
+            style(ReadableDate(entry.detected_at)).yellow(),
+            format_changes(&entry)
+        );
+    }
+
+    Ok(())
+}
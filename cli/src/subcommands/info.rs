@@ -0,0 +1,93 @@
+use console::style;
+
+use fimfic_tracker::{archive, download_path, Config, Result, StoryData};
+
+use crate::args::Info;
+use crate::readable::ReadableDate;
+
+use super::list::{format_optional_date, format_tags_note};
+use super::set::print_overrides;
+
+pub fn info(config: &Config, story_data: &StoryData, Info { id }: Info) -> Result<()> {
+    let story = match story_data.get(&id) {
+        Some(story) => story,
+        None => {
+            warn!(
+                "There is no story of ID {}{}",
+                style(id).bold(),
+                style(" on the tracking list.").yellow()
+            );
+            return Ok(());
+        }
+    };
+
+    let story_config = config.for_story(story);
+
+    let mut lines = vec![
+        format!("{}", style(format_args!("[{}]", story.id)).blue().bold()),
+        format!("url = {}", style(story.url_in(config)).cyan()),
+        format!("title = {}", style(&story.title).green()),
+        format!("author = {}", style(&story.author).green()),
+        format!("chapter-amt = {}", style(story.chapter_count).blue()),
+        format!("words = {}", style(story.words).blue()),
+        format!(
+            "last-update-date = {}",
+            style(ReadableDate(story.update_datetime)).yellow()
+        ),
+        format!("status = {}", format_status!(story)),
+        format!(
+            "added-date = {}",
+            format_optional_date(story.added_at, "unknown")
+        ),
+        format!(
+            "last-check-date = {}",
+            format_optional_date(story.last_checked_at, "never")
+        ),
+        format!(
+            "last-download-date = {}",
+            format_optional_date(story.last_downloaded_at, "never")
+        ),
+    ];
+
+    lines.extend(format_tags_note(story));
+
+    if let Some(path) = story.last_download_path.as_ref() {
+        lines.push(format!(
+            "last-download-file = {}",
+            style(path.display()).green()
+        ));
+    }
+
+    match story_config.exec.as_ref() {
+        Some(exec) => lines.push(format!("exec = {}", style(exec).cyan())),
+        None => {
+            for format in story_config.download_formats.iter().copied() {
+                let path = download_path(story, format, &story_config);
+                let missing = if path.is_file() {
+                    ""
+                } else {
+                    " (not downloaded)"
+                };
+
+                lines.push(format!(
+                    "{}-file = {}{}",
+                    format,
+                    style(path.display()).green(),
+                    style(missing).dim()
+                ));
+            }
+        }
+    }
+
+    lines.push(format!(
+        "previous-copies = {}",
+        style(archive::versions(story, config)?.len()).blue()
+    ));
+
+    println!("{}", lines.join("\n"));
+
+    info!("Values set for this story:");
+    print_overrides(&story.overrides);
+
+    Ok(())
+}
@@ -1,10 +1,179 @@
+use chrono::{DateTime, SecondsFormat, Utc};
 use console::style;
+use serde::Serialize;
 
-use fimfic_tracker::{Story, StoryData, StoryStatus};
+use fimfic_tracker::{Config, Story, StoryData, StoryStatus};
 
-use crate::args::{List, SortKey};
+use crate::args::{List, ListFormat, SortKey};
 use crate::readable::ReadableDate;
 
+/// A tracked story as output by the machine-readable formats.
+#[derive(Serialize)]
+struct Record<'a> {
+    id: u32,
+    url: String,
+    title: &'a str,
+    author: &'a str,
+    chapters: u64,
+    words: u64,
+    last_update: String,
+    status: String,
+    added: Option<String>,
+    last_check: Option<String>,
+    last_download: Option<String>,
+    last_download_path: Option<String>,
+    tags: Vec<&'a str>,
+    note: Option<&'a str>,
+}
+
+fn rfc3339(date: DateTime<Utc>) -> String {
+    date.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// The `tags` and `note` lines of `story`, for those it has.
+pub(super) fn format_tags_note(story: &Story) -> Vec<String> {
+    let mut lines = Vec::new();
+
+    if !story.tags.is_empty() {
+        lines.push(format!(
+            "tags = {}",
+            style(story.tags.iter().cloned().collect::<Vec<_>>().join(", ")).cyan()
+        ));
+    }
+
+    if let Some(note) = story.note.as_ref() {
+        lines.push(format!("note = {}", style(format!("{:?}", note)).green()));
+    }
+
+    lines
+}
+
+/// `date` in a readable form, or `missing` dimmed if there is none.
+pub(super) fn format_optional_date(date: Option<DateTime<Utc>>, missing: &str) -> String {
+    match date {
+        Some(date) => style(ReadableDate(date)).yellow().to_string(),
+        None => style(missing).dim().to_string(),
+    }
+}
+
+impl<'a> Record<'a> {
+    fn new(story: &'a Story, config: &Config) -> Self {
+        Record {
+            id: story.id,
+            url: story.url_in(config),
+            title: &story.title,
+            author: &story.author,
+            chapters: story.chapter_count,
+            words: story.words,
+            last_update: rfc3339(story.update_datetime),
+            status: story.status.to_string(),
+            added: story.added_at.map(rfc3339),
+            last_check: story.last_checked_at.map(rfc3339),
+            last_download: story.last_downloaded_at.map(rfc3339),
+            last_download_path: story
+                .last_download_path
+                .as_ref()
+                .map(|path| path.display().to_string()),
+            tags: story.tags.iter().map(|tag| tag.as_str()).collect(),
+            note: story.note.as_deref(),
+        }
+    }
+}
+
+impl Record<'_> {
+    const HEADER: [&'static str; 14] = [
+        "id",
+        "url",
+        "title",
+        "author",
+        "chapters",
+        "words",
+        "last_update",
+        "status",
+        "added",
+        "last_check",
+        "last_download",
+        "last_download_path",
+        "tags",
+        "note",
+    ];
+
+    fn fields(&self) -> [String; 14] {
+        [
+            self.id.to_string(),
+            self.url.clone(),
+            self.title.to_string(),
+            self.author.to_string(),
+            self.chapters.to_string(),
+            self.words.to_string(),
+            self.last_update.clone(),
+            self.status.clone(),
+            self.added.clone().unwrap_or_default(),
+            self.last_check.clone().unwrap_or_default(),
+            self.last_download.clone().unwrap_or_default(),
+            self.last_download_path.clone().unwrap_or_default(),
+            self.tags.join(","),
+            self.note.unwrap_or_default().to_string(),
+        ]
+    }
+}
+
+/// Quotes `field` for CSV if it contains a separator, a quote or a line break.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Replaces the characters that would break a TSV row in `field` with spaces.
+fn tsv_field(field: &str) -> String {
+    field.replace(['\t', '\n', '\r'], " ")
+}
+
+/// Prints `stories` in the given machine-readable `format` into stdout.
+fn print_formatted(config: &Config, stories: &[&Story], format: ListFormat) {
+    let records = stories
+        .iter()
+        .map(|story| Record::new(story, config))
+        .collect::<Vec<Record>>();
+
+    match format {
+        ListFormat::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&records).expect("story records should serialize")
+        ),
+        ListFormat::Jsonl => {
+            for record in records {
+                println!(
+                    "{}",
+                    serde_json::to_string(&record).expect("story records should serialize")
+                );
+            }
+        }
+        ListFormat::Csv | ListFormat::Tsv => {
+            let (separator, escape): (&str, fn(&str) -> String) = match format {
+                ListFormat::Csv => (",", csv_field),
+                _ => ("\t", tsv_field),
+            };
+
+            println!("{}", Record::HEADER.join(separator));
+            for record in records {
+                println!(
+                    "{}",
+                    record
+                        .fields()
+                        .iter()
+                        .map(|field| escape(field))
+                        .collect::<Vec<String>>()
+                        .join(separator)
+                );
+            }
+        }
+    }
+}
+
 macro_rules! sort_by_attr_funcs {
     ($(fn $name:ident (.$attr:ident) -> Ordering;)+) => {
         $(
@@ -22,14 +191,20 @@ sort_by_attr_funcs! {
     fn sort_by_chapters(.chapter_count) -> Ordering;
     fn sort_by_words(.words) -> Ordering;
     fn sort_by_update(.update_datetime) -> Ordering;
+    fn sort_by_added(.added_at) -> Ordering;
+    fn sort_by_checked(.last_checked_at) -> Ordering;
+    fn sort_by_downloaded(.last_downloaded_at) -> Ordering;
 }
 
 pub fn list(
+    config: &Config,
     story_data: &StoryData,
     List {
         short,
         sort_by,
         reverse,
+        format,
+        ref tag,
         status_filter,
     }: List,
 ) {
@@ -43,6 +218,9 @@ pub fn list(
             SortKey::Chapters => sort_by_chapters,
             SortKey::Words => sort_by_words,
             SortKey::Update => sort_by_update,
+            SortKey::Added => sort_by_added,
+            SortKey::Checked => sort_by_checked,
+            SortKey::Downloaded => sort_by_downloaded,
         };
 
         stories.sort_by(|a, b| sorter(a, b));
@@ -52,19 +230,30 @@ pub fn list(
         stories.reverse();
     }
 
-    let output_format = if short {
-        |story: &Story| {
+    stories.retain(|story| match story.status {
+        StoryStatus::Complete => status_filter.complete(),
+        StoryStatus::Incomplete => status_filter.incomplete(),
+        StoryStatus::Hiatus => status_filter.hiatus(),
+        StoryStatus::Cancelled => status_filter.cancelled(),
+    });
+    stories.retain(|story| tag.iter().all(|tag| story.tags.contains(tag)));
+
+    if let Some(format) = format {
+        print_formatted(config, &stories, format);
+        return;
+    }
+
+    let output_format = |story: &Story| {
+        if short {
             format!(
                 "{} {}",
                 style(format_args!("{}", story.id)).blue(),
                 style(&story.title).green()
             )
-        }
-    } else {
-        |story: &Story| {
+        } else {
             [
                 format!("{}", style(format_args!("[{}]", story.id)).blue().bold()),
-                format!("url = {}", style(story.url()).cyan()),
+                format!("url = {}", style(story.url_in(config)).cyan()),
                 format!("title = {}", style(&story.title).green()),
                 format!("author = {}", style(&story.author).green()),
                 format!("chapter-amt = {}", style(story.chapter_count).blue()),
@@ -74,7 +263,22 @@ pub fn list(
                     style(ReadableDate(story.update_datetime)).yellow()
                 ),
                 format!("status = {}", format_status!(story)),
+                format!(
+                    "added-date = {}",
+                    format_optional_date(story.added_at, "unknown")
+                ),
+                format!(
+                    "last-check-date = {}",
+                    format_optional_date(story.last_checked_at, "never")
+                ),
+                format!(
+                    "last-download-date = {}",
+                    format_optional_date(story.last_downloaded_at, "never")
+                ),
             ]
+            .into_iter()
+            .chain(format_tags_note(story))
+            .collect::<Vec<String>>()
             .join("\n")
         }
     };
@@ -85,14 +289,6 @@ pub fn list(
         "{}",
         stories
             .drain(..)
-            .filter(|story| {
-                match story.status {
-                    StoryStatus::Complete => status_filter.complete(),
-                    StoryStatus::Incomplete => status_filter.incomplete(),
-                    StoryStatus::Hiatus => status_filter.hiatus(),
-                    StoryStatus::Cancelled => status_filter.cancelled(),
-                }
-            })
             .map(output_format)
             .collect::<Vec<String>>()
             .join(sep),
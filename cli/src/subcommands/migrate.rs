@@ -0,0 +1,29 @@
+use console::style;
+
+use fimfic_tracker::{StoryData, TRACKER_FORMAT_VERSION};
+
+pub fn migrate(story_data: &StoryData) {
+    let migrations = story_data.migrations();
+
+    if migrations.is_empty() {
+        info!(
+            "The tracker file is already in the current format (version {})",
+            style(TRACKER_FORMAT_VERSION).blue()
+        );
+        return;
+    }
+
+    for migration in migrations {
+        info!(
+            "Migrating from version {} to {}: {}",
+            style(migration.from).blue(),
+            style(migration.to).blue().bold(),
+            migration.description
+        );
+    }
+
+    info!(
+        "Rewriting the tracker file, its previous version is kept in `{}`",
+        style(story_data.backup_path().display()).green()
+    );
+}
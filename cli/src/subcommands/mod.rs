@@ -1,9 +1,29 @@
+mod cache;
+mod check;
 mod download;
+mod history;
+mod info;
 mod list;
+mod migrate;
+mod note;
+mod report;
+mod set;
+mod tag;
 mod track;
 mod untrack;
+mod updates;
+mod versions;
 
+pub use cache::cache;
+pub use check::check;
 pub use download::download;
+pub use history::history;
+pub use info::info;
 pub use list::list;
+pub use migrate::migrate;
+pub use note::note;
+pub use set::set;
+pub use tag::tag;
 pub use track::track;
 pub use untrack::untrack;
+pub use versions::versions;
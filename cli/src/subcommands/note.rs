@@ -0,0 +1,38 @@
+use console::style;
+
+use fimfic_tracker::StoryData;
+
+use crate::args::Note;
+
+pub fn note(story_data: &mut StoryData, Note { remove, id, note }: Note) {
+    let story = match story_data.get_mut(&id) {
+        Some(story) => story,
+        None => {
+            warn!(
+                "There is no story of ID {}{}",
+                style(id).bold(),
+                style(" on the tracking list.").yellow()
+            );
+            return;
+        }
+    };
+
+    if remove {
+        match story.note.take() {
+            Some(_) => info!("Removed the note of {}", format_story!(story)),
+            None => info!("{} has no note", format_story!(story)),
+        }
+        return;
+    }
+
+    if let Some(note) = note {
+        story.note = Some(note);
+        info!("Wrote the note of {}", format_story!(story));
+        return;
+    }
+
+    match story.note.as_ref() {
+        Some(note) => println!("{}", note),
+        None => info!("{} has no note", format_story!(story)),
+    }
+}
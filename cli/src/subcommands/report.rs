@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use fimfic_tracker::{
+    checker::{StoryCheck, StoryFailure},
+    downloader::Downloaded,
+    Id, Result, Story, StoryData, StoryUpdate,
+};
+
+use super::updates::Checked;
+
+/// What happened to a story during a `download` run.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum EntryResult {
+    /// Had a relevant update and was downloaded.
+    Updated,
+    /// Was downloaded without a relevant update because of `--force`.
+    Forced,
+    /// Had a relevant update, but the run stopped on an error before downloading it.
+    NotDownloaded,
+    /// Had an update that wasn't relevant enough to be downloaded, or the story is set to skip
+    /// its downloads.
+    Ignored,
+    /// Changed its title, author or status without an update.
+    Changed,
+    /// Had no changes.
+    Unchanged,
+    /// Wasn't checked because of the prompt.
+    Skipped,
+    /// Failed to be checked or downloaded.
+    Failed,
+}
+
+/// How a story was downloaded.
+#[derive(Serialize, Debug)]
+#[serde(tag = "method", rename_all = "snake_case")]
+enum DownloadEntry {
+    /// Downloaded from Fimfiction into `paths`, one for each format, where the ones in
+    /// `unchanged` were left untouched for having the same content as before.
+    File {
+        paths: Vec<PathBuf>,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        unchanged: Vec<PathBuf>,
+    },
+    /// Handed to the `exec` command.
+    Command { exit_code: Option<i32> },
+}
+
+impl From<&Downloaded> for DownloadEntry {
+    fn from(downloaded: &Downloaded) -> Self {
+        match downloaded {
+            Downloaded::Files(files) => DownloadEntry::File {
+                paths: files.iter().map(|file| file.path.clone()).collect(),
+                unchanged: files
+                    .iter()
+                    .filter(|file| file.unchanged)
+                    .map(|file| file.path.clone())
+                    .collect(),
+            },
+            Downloaded::Command(status) => DownloadEntry::Command {
+                exit_code: status.code(),
+            },
+        }
+    }
+}
+
+/// A story in the report.
+#[derive(Serialize, Debug)]
+struct Entry<'a> {
+    id: Id,
+    result: EntryResult,
+    before: &'a Story,
+    after: Option<&'a Story>,
+    update: Option<&'a StoryUpdate>,
+    title_changed: bool,
+    author_changed: bool,
+    status_changed: bool,
+    download: Option<DownloadEntry>,
+    failed_at: Option<String>,
+    error: Option<String>,
+}
+
+impl<'a> Entry<'a> {
+    fn new(before: &'a Story, result: EntryResult) -> Self {
+        Entry {
+            id: before.id,
+            result,
+            before,
+            after: None,
+            update: None,
+            title_changed: false,
+            author_changed: false,
+            status_changed: false,
+            download: None,
+            failed_at: None,
+            error: None,
+        }
+    }
+
+    fn from_check(check: &'a StoryCheck) -> Self {
+        let result = if check.is_ignored() || check.is_skipped() {
+            EntryResult::Ignored
+        } else if check.should_download() {
+            EntryResult::NotDownloaded
+        } else if check.has_metadata_changes() && check.update.is_none() {
+            EntryResult::Changed
+        } else {
+            EntryResult::Unchanged
+        };
+
+        Entry {
+            after: Some(&check.after),
+            update: check.update.as_ref(),
+            title_changed: check.title_changed(),
+            author_changed: check.author_changed(),
+            status_changed: check.status_changed(),
+            ..Entry::new(&check.before, result)
+        }
+    }
+}
+
+/// Structured report of a `download` run.
+#[derive(Serialize, Debug)]
+pub struct Report<'a> {
+    stories: Vec<Entry<'a>>,
+}
+
+impl<'a> Report<'a> {
+    /// Constructs the report of the stories handled in `checked`, in the order of `story_data`,
+    /// where `downloads` are the stories that were successfully downloaded and `aborted` the
+    /// failure that stopped the run, if any.
+    pub fn new(
+        story_data: &'a StoryData,
+        checked: &'a Checked,
+        downloads: &HashMap<Id, Downloaded>,
+        aborted: Option<&'a StoryFailure>,
+    ) -> Self {
+        let checks: HashMap<Id, &StoryCheck> = checked
+            .report
+            .checked()
+            .map(|check| (check.id(), check))
+            .collect();
+
+        let mut stories = Vec::new();
+
+        for (id, story) in story_data.iter() {
+            let failure = checked
+                .report
+                .failures()
+                .chain(aborted)
+                .find(|failure| failure.story.id == *id);
+
+            let mut entry = match checks.get(id) {
+                Some(check) => Entry::from_check(check),
+                None if checked.skipped_ids.contains(id) => Entry::new(story, EntryResult::Skipped),
+                None => match failure {
+                    Some(failure) => Entry::new(&failure.story, EntryResult::Failed),
+                    None => continue,
+                },
+            };
+
+            if let Some(downloaded) = downloads.get(id) {
+                entry.result = if matches!(checks.get(id), Some(check) if check.should_download()) {
+                    EntryResult::Updated
+                } else {
+                    EntryResult::Forced
+                };
+                entry.download = Some(downloaded.into());
+            }
+
+            if let Some(failure) = failure {
+                entry.result = EntryResult::Failed;
+                entry.failed_at = Some(failure.stage.to_string());
+                entry.error = Some(failure.error.to_string());
+            }
+
+            stories.push(entry);
+        }
+
+        Report { stories }
+    }
+
+    /// Serializes the report as pretty-printed JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("download report should serialize")
+    }
+
+    /// Writes the report as JSON into `path`, atomically.
+    pub fn write<P>(&self, path: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        fimfic_tracker::write(path, self.to_json())
+    }
+}
@@ -0,0 +1,116 @@
+use console::style;
+
+use fimfic_tracker::{StoryData, StoryOverrides};
+
+use crate::args::{OverrideKey, Set};
+
+/// Prints the values set in `overrides`, one per line.
+pub(super) fn print_overrides(overrides: &StoryOverrides) {
+    if overrides.is_empty() {
+        info!(
+            "  {}",
+            style("Nothing set, using the configured values").dim()
+        );
+        return;
+    }
+
+    macro_rules! print_value {
+        ($name:literal, $value:expr) => {
+            if let Some(value) = $value {
+                info!("  {} = {}", style($name).bold(), style(value).blue());
+            }
+        };
+    }
+
+    print_value!(
+        "download_format",
+        overrides.download_format.as_ref().map(|formats| formats
+            .iter()
+            .map(|format| format.to_string())
+            .collect::<Vec<_>>()
+            .join(", "))
+    );
+    print_value!(
+        "exec",
+        overrides.exec.as_ref().map(|exec| format!("{:?}", exec))
+    );
+    print_value!(
+        "sensibility_level",
+        overrides.sensibility_level.map(|level| level as u8)
+    );
+    print_value!("download_dir", overrides.download_dir.as_ref());
+    print_value!("skip_download", overrides.skip_download);
+}
+
+pub fn set(
+    story_data: &mut StoryData,
+    Set {
+        download_format: formats,
+        exec,
+        sensibility_level: level,
+        download_dir,
+        skip_download,
+        ref unset,
+        ref ids,
+    }: Set,
+) {
+    let has_changes = formats.is_some()
+        || exec.is_some()
+        || level.is_some()
+        || download_dir.is_some()
+        || skip_download.is_some()
+        || !unset.is_empty();
+
+    for id in ids {
+        let story = match story_data.get_mut(id) {
+            Some(story) => story,
+            None => {
+                warn!(
+                    "There is no story of ID {}{}",
+                    style(id).bold(),
+                    style(" on the tracking list.").yellow()
+                );
+                continue;
+            }
+        };
+
+        let overrides = &mut story.overrides;
+
+        for key in unset {
+            match key {
+                OverrideKey::DownloadFormat => overrides.download_format = None,
+                OverrideKey::Exec => overrides.exec = None,
+                OverrideKey::SensibilityLevel => overrides.sensibility_level = None,
+                OverrideKey::DownloadDir => overrides.download_dir = None,
+                OverrideKey::SkipDownload => overrides.skip_download = None,
+            }
+        }
+
+        if let Some(formats) = formats.as_ref() {
+            overrides.download_format = Some(formats.clone());
+        }
+
+        if let Some(exec) = exec.as_ref() {
+            overrides.exec = Some(exec.clone());
+        }
+
+        if let Some(level) = level {
+            overrides.sensibility_level = Some(level);
+        }
+
+        if let Some(path) = download_dir.as_ref() {
+            overrides.download_dir = Some(path.clone());
+        }
+
+        if let Some(skip) = skip_download {
+            overrides.skip_download = Some(skip);
+        }
+
+        if has_changes {
+            info!("Updated values of {}:", format_story!(story));
+        } else {
+            info!("Values of {}:", format_story!(story));
+        }
+        print_overrides(&story.overrides);
+    }
+}
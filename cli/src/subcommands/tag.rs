@@ -0,0 +1,52 @@
+use console::style;
+
+use fimfic_tracker::StoryData;
+
+use crate::args::{Tag, TagAction};
+
+pub fn tag(story_data: &mut StoryData, Tag { action }: Tag) {
+    let (id, tags, add) = match action {
+        TagAction::Add { id, tags } => (id, tags, true),
+        TagAction::Remove { id, tags } => (id, tags, false),
+    };
+
+    let story = match story_data.get_mut(&id) {
+        Some(story) => story,
+        None => {
+            warn!(
+                "There is no story of ID {}{}",
+                style(id).bold(),
+                style(" on the tracking list.").yellow()
+            );
+            return;
+        }
+    };
+
+    for tag in tags {
+        if add {
+            if !story.tags.insert(tag.clone()) {
+                info!(
+                    "{} already has tag {}",
+                    format_story!(story),
+                    style(tag).cyan()
+                );
+            }
+        } else if !story.tags.remove(&tag) {
+            info!(
+                "{} doesn't have tag {}",
+                format_story!(story),
+                style(tag).cyan()
+            );
+        }
+    }
+
+    if story.tags.is_empty() {
+        info!("{} has no tags", format_story!(story));
+    } else {
+        info!(
+            "Tags of {}: {}",
+            format_story!(story),
+            style(story.tags.iter().cloned().collect::<Vec<_>>().join(", ")).cyan()
+        );
+    }
+}
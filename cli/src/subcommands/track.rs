@@ -1,3 +1,4 @@
+use chrono::Utc;
 use console::style;
 use dialoguer::Confirm;
 
@@ -59,7 +60,15 @@ pub fn track(
 
     for id in to_track {
         progress_or_info!("Downloading story data for {}", style(id).blue());
-        let story: Story = requester.get_story_response(id)?.into();
+        let mut story: Story = requester.get_story_response(id)?.into();
+
+        // Overwriting a story keeps what was set and tracked for it.
+        let now = Utc::now();
+        match story_data.get(&id) {
+            Some(previous) => story.keep_tracking_data(previous),
+            None => story.added_at = Some(now),
+        }
+        story.last_checked_at = Some(now);
 
         story_data.insert(id, story.clone());
 
@@ -83,11 +92,16 @@ pub fn track(
 
     for (is_first, story) in stories
         .drain(..)
+        .filter(|story| !story.overrides.skip_download())
         .enumerate()
         .map(|(index, story)| (index == 0, story))
     {
         download_delay!(!is_first, use_separator, delay);
-        requester.download(&story)?;
+        let downloaded = requester.download(&story)?;
+
+        if let Some(story) = story_data.get_mut(&story.id) {
+            downloaded.record_download(story, Utc::now());
+        }
     }
 
     Ok(())
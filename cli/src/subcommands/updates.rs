@@ -0,0 +1,370 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Utc};
+use console::style;
+use dialoguer::Confirm;
+
+use fimfic_tracker::{
+    checker::{self, CheckReport, StoryCheck, StoryFailure},
+    history, Config, Id, Result, Story, StoryData, StoryStatus, StoryUpdate, TrackerError,
+};
+
+use crate::args::Prompt;
+use crate::readable::ReadableDate;
+use crate::Requester;
+
+/// How a run that didn't stop on the first error ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// Every story was handled successfully.
+    Success,
+    /// Some of the stories failed.
+    PartialFailure,
+    /// Every story failed.
+    Failure,
+}
+
+impl Outcome {
+    /// The exit code of the application for this outcome.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Outcome::Success => 0,
+            Outcome::Failure => 1,
+            Outcome::PartialFailure => 2,
+        }
+    }
+}
+
+macro_rules! format_update {
+    (author, $before:expr => $after:expr) => {
+        format_update!([green] &$before, &$after)
+    };
+    (chapters, $before:expr => $after:expr) => {
+        format_update!([blue] $before, $after)
+    };
+    (words, $before:expr => $after:expr) => {
+        format_update!([blue] $before, $after)
+    };
+    (timestamp, $before:expr => $after:expr) => {
+        format_update!([yellow] ReadableDate($before), ReadableDate($after))
+    };
+    (status, $before:expr => $after:expr) => {
+        format_update!([yellow] $before, $after)
+    };
+    ([$color:ident] $before:expr, $after:expr) => {
+        format_args!(
+            "{} {} {}",
+            style($before).$color(),
+            style("=>").cyan(),
+            style($after).$color().bold()
+        )
+    };
+}
+
+macro_rules! info_story_checking {
+    ($story:expr) => {
+        info!("Checking for {} ...", format_story!($story));
+    };
+}
+
+macro_rules! info_update {
+    ([ignored] $story:expr, $on:ident, $before:expr => $after:expr) => {
+        info_update!($story, $on, $before, $after, ". Ignoring")
+    };
+    ([skipped] $story:expr, $on:ident, $before:expr => $after:expr) => {
+        info_update!($story, $on, $before, $after, ". Not downloading it, as set for the story")
+    };
+    ($story:expr, $on:ident, $before:expr => $after:expr) => {
+        info_update!($story, $on, $before, $after, "")
+    };
+    ($story:expr, $on:ident, $before:expr, $after:expr, $extra:expr) => {
+        info!(
+            "{} has an update on {} ({}){}",
+            format_story!($story),
+            stringify!($on),
+            format_update!($on, $before => $after),
+            $extra
+        );
+    };
+}
+
+/// Result of [`check_updates()`].
+pub struct Checked {
+    /// The checks and failures of the stories.
+    pub report: CheckReport,
+    /// IDs of the stories skipped by the prompt.
+    pub skipped_ids: HashSet<Id>,
+    /// Amount of stories that were checked, successfully or not.
+    pub checked_amount: usize,
+    /// Whether the checks printed anything.
+    pub printed: bool,
+    /// When the checks were done.
+    pub checked_at: DateTime<Utc>,
+}
+
+impl Checked {
+    /// The outcome of the run, according to the amount of stories that failed.
+    pub fn outcome(&self, story_data: &StoryData) -> Outcome {
+        if self.report.failures().next().is_none() {
+            return Outcome::Success;
+        }
+
+        let failed_amount = story_data
+            .keys()
+            .filter(|id| self.report.has_failed(**id))
+            .count();
+
+        if failed_amount < self.checked_amount {
+            Outcome::PartialFailure
+        } else {
+            Outcome::Failure
+        }
+    }
+}
+
+/// Checks for updates the stories with an ID in `ids`, or all of them if empty, with
+/// [`checker::check_updates()`], printing the changes found.
+///
+/// Before that, it asks through `prompt` whether to check the stories that aren't incomplete.
+pub fn check_updates(
+    config: &Config,
+    requester: &Requester,
+    story_data: &StoryData,
+    ids: &[Id],
+    prompt: Prompt,
+    keep_going: bool,
+) -> Result<Checked> {
+    let selected_ids: Vec<Id> = if ids.is_empty() {
+        story_data.keys().cloned().collect()
+    } else {
+        story_data
+            .keys()
+            .filter(|id| ids.contains(id))
+            .cloned()
+            .collect()
+    };
+    let mut ignored_ids: HashSet<Id> = HashSet::with_capacity(selected_ids.len());
+
+    let mut printed = false;
+
+    macro_rules! set_printed {
+        () => {
+            if !printed {
+                printed = true;
+            }
+        };
+    }
+
+    for (id, story) in story_data.iter().filter_map(|(id, story)| {
+        if selected_ids.contains(id) {
+            Some((*id, story))
+        } else {
+            None
+        }
+    }) {
+        if let StoryStatus::Incomplete = story.status {
+            continue;
+        }
+
+        set_printed!();
+
+        let status_notice = format!(
+            "{} has been marked as {} by the author",
+            format_story!(story),
+            format_status!(story)
+        );
+
+        match prompt {
+            Prompt::AssumeYes => {
+                info!("{}. Checking for an update on it anyways.", status_notice);
+            }
+            Prompt::AssumeNo => {
+                info!("{}. Skipping checking for an update on it.", status_notice);
+                ignored_ids.insert(id);
+            }
+            Prompt::Ask => {
+                let confirm = Confirm::new()
+                    .with_prompt(format!(
+                        "{}. Do you want to still check for an update on it?",
+                        status_notice
+                    ))
+                    .interact()
+                    .map_err(|err| {
+                        TrackerError::io(err)
+                            .context("failed to launch overwrite confirmation prompt")
+                    })?;
+
+                if !confirm {
+                    ignored_ids.insert(id);
+                }
+            }
+        }
+    }
+
+    if printed {
+        separate!();
+        printed = false;
+    }
+
+    let stories_to_check: Vec<&Story> = story_data
+        .values()
+        .filter(|story| selected_ids.contains(&story.id) && !ignored_ids.contains(&story.id))
+        .collect();
+
+    progress_or_info!(
+        "Checking {} stories for updates ...",
+        style(stories_to_check.len()).blue().bold()
+    );
+    let report = checker::check_updates(
+        requester,
+        stories_to_check.iter().copied(),
+        config,
+        keep_going,
+    )?;
+    clear_last_lines!();
+
+    let checks: HashMap<Id, &StoryCheck> =
+        report.checked().map(|check| (check.id(), check)).collect();
+    let failures: HashMap<Id, &StoryFailure> = report
+        .failures()
+        .map(|failure| (failure.story.id, failure))
+        .collect();
+
+    // The results are printed one by one as if the stories were checked sequentially.
+    for story in stories_to_check.iter().copied() {
+        info_story_checking!(story);
+
+        if let Some(failure) = failures.get(&story.id) {
+            set_printed!();
+            clear_last_lines!();
+            warn!(
+                "Failed to check {}: {}",
+                format_story!(story),
+                failure.error
+            );
+            continue;
+        }
+
+        let check = match checks.get(&story.id) {
+            Some(check) => *check,
+            None => continue,
+        };
+        let updated_story = &check.after;
+
+        if check.has_changes() {
+            // If we are here, something will be printed to stderr. Be it by the specific cases
+            // just below or by the resulting StoryUpdate comparison.
+            set_printed!();
+
+            if check.has_metadata_changes() {
+                clear_last_lines!();
+
+                if check.title_changed() {
+                    info!(
+                        "{} has changed its title to {}",
+                        format_story!(story),
+                        style(&updated_story.title).green().bold()
+                    );
+                }
+
+                if check.author_changed() {
+                    info!(
+                        "{} has changed its author ({})",
+                        format_story!(story),
+                        format_update!(author, story.author => updated_story.author)
+                    );
+                }
+
+                if check.status_changed() {
+                    info!(
+                        "{} has changed its status ({})",
+                        format_story!(story),
+                        format_update!(status, story.status => updated_story.status),
+                    );
+                }
+
+                // Avoid this message from being repeated twice in verbose output.
+                if verbose_disabled!() {
+                    info_story_checking!(story);
+                }
+            }
+        }
+
+        clear_last_lines!();
+
+        match check.update {
+            Some(StoryUpdate::Chapters { before, after }) if check.is_skipped() => {
+                info_update!([skipped] story, chapters, before => after);
+            }
+            Some(StoryUpdate::Words { before, after }) if check.is_skipped() => {
+                info_update!([skipped] story, words, before => after);
+            }
+            Some(StoryUpdate::DateTime { before, after }) if check.is_skipped() => {
+                info_update!([skipped] story, timestamp, before => after);
+            }
+            Some(StoryUpdate::Chapters { before, after }) => {
+                info_update!(story, chapters, before => after);
+            }
+            Some(StoryUpdate::Words { before, after }) if check.should_download() => {
+                info_update!(story, words, before => after);
+            }
+            Some(StoryUpdate::DateTime { before, after }) if check.should_download() => {
+                info_update!(story, timestamp, before => after);
+            }
+            Some(StoryUpdate::Words { before, after }) => {
+                info_update!([ignored] story, words, before => after);
+            }
+            Some(StoryUpdate::DateTime { before, after }) => {
+                info_update!([ignored] story, timestamp, before => after);
+            }
+            None => {}
+        };
+    }
+
+    let checked_amount = stories_to_check.len();
+
+    let checked_at = Utc::now();
+    if let Err(err) = history::record(report.changes(), checked_at, config) {
+        warn!("Failed to record the changes found in the history: {}", err);
+    }
+
+    Ok(Checked {
+        report,
+        skipped_ids: ignored_ids,
+        checked_amount,
+        printed,
+        checked_at,
+    })
+}
+
+/// Prints a table with the stories that failed in `report`, where `action` is what was being
+/// done with them.
+pub fn print_failures(report: &CheckReport, action: &str) {
+    let failures = report.failures().collect::<Vec<_>>();
+    let id_width = failures
+        .iter()
+        .map(|failure| failure.story.id.to_string().len())
+        .max()
+        .unwrap_or_default();
+
+    separate!();
+    error!(
+        "{} failed while {}:",
+        match failures.len() {
+            1 => "A story".to_string(),
+            amount => format!("{} stories", amount),
+        },
+        action
+    );
+
+    for failure in failures {
+        error!(
+            "  {:>width$}  {:<8}  {}: {}",
+            failure.story.id,
+            failure.stage,
+            failure.story.title,
+            failure.error,
+            width = id_width
+        );
+    }
+}
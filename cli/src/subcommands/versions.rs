@@ -0,0 +1,49 @@
+use console::style;
+
+use fimfic_tracker::{archive, Config, Result, StoryData};
+
+use crate::args::Versions;
+use crate::readable::{ReadableBytes, ReadableDate};
+
+pub fn versions(config: &Config, story_data: &StoryData, Versions { id }: Versions) -> Result<()> {
+    let story = match story_data.get(&id) {
+        Some(story) => story,
+        None => {
+            warn!(
+                "There is no story of ID {}{}",
+                style(id).bold(),
+                style(" on the tracking list.").yellow()
+            );
+            return Ok(());
+        }
+    };
+
+    let versions = archive::versions(story, config)?;
+
+    if versions.is_empty() {
+        info!("There are no previous copies of {}", format_story!(story));
+        if !config.for_story(story).archive {
+            info!(
+                "{}",
+                style("Enable the `archive` option to keep them when downloading").dim()
+            );
+        }
+        return Ok(());
+    }
+
+    info!(
+        "Previous copies of {}, from the oldest to the most recent:",
+        format_story!(story)
+    );
+
+    for version in versions {
+        println!(
+            "{}  {}  {}",
+            style(ReadableDate(version.downloaded_at)).yellow(),
+            style(ReadableBytes(version.size as usize)).blue(),
+            style(version.path.display()).green()
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,354 @@
+//! Previous copies of downloaded stories.
+//!
+//! With [`Config::archive`](crate::Config::archive) enabled, the file of a story that is about to
+//! be downloaded again is kept in [`archive_dir()`] before being replaced, with the date it was
+//! downloaded at prepended to its name, followed by a counter if there is already a copy with that
+//! name. Only the last
+//! [`Config::archive_retention`](crate::Config::archive_retention) copies of each file extension
+//! are kept, if set.
+//! ```no_run
+//! # use fimfic_tracker::Result;
+//! # fn main() -> Result<()> {
+//! use fimfic_tracker::{archive, Config, StoryData};
+//! # let config = Config::default();
+//!
+//! let mut story_data = StoryData::new(&config.tracker_file);
+//! story_data.load()?;
+//!
+//! for story in story_data.values() {
+//!     for version in archive::versions(story, &config)? {
+//!         println!("{}: {}", version.downloaded_at, version.path.display());
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+
+use crate::config::Config;
+use crate::errors::{self, TrackerError};
+use crate::story::Story;
+
+#[cfg(feature = "downloader")]
+use std::path::Path;
+
+/// Format of the date prepended to the name of the archived copies.
+const DATE_FORMAT: &str = "%Y-%m-%d_%H-%M-%S";
+
+/// A previous copy of a story, as given by [`versions()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Version {
+    /// Path to the copy.
+    pub path: PathBuf,
+    /// The name the file had before being archived.
+    pub filename: String,
+    /// When the copy was downloaded.
+    pub downloaded_at: DateTime<Utc>,
+    /// Size of the copy in bytes.
+    pub size: u64,
+}
+
+/// Path to the directory with the previous copies of `story`, in the `.archive` directory inside
+/// of the download directory that applies to it.
+pub fn archive_dir(story: &Story, config: &Config) -> PathBuf {
+    config
+        .for_story(story)
+        .download_dir
+        .join(".archive")
+        .join(story.id.to_string())
+}
+
+/// Splits the name of an archived copy into the date it was downloaded at and its filename,
+/// ignoring the counter after the date if there is one.
+fn parse_name(name: &str) -> Option<(DateTime<Utc>, &str)> {
+    let (date, filename) = name.split_once(' ')?;
+    let date = NaiveDateTime::parse_from_str(date, DATE_FORMAT)
+        .ok()
+        .or_else(|| {
+            let (date, counter) = date.rsplit_once('_')?;
+            counter.parse::<u32>().ok()?;
+            NaiveDateTime::parse_from_str(date, DATE_FORMAT).ok()
+        })?;
+    Some((Utc.from_utc_datetime(&date), filename))
+}
+
+/// The previous copies of `story` that are in its [`archive_dir()`], from the oldest to the most
+/// recent.
+///
+/// # Errors
+///
+/// If the archive directory exists but can't be read.
+pub fn versions(story: &Story, config: &Config) -> errors::Result<Vec<Version>> {
+    let dir = archive_dir(story, config);
+    let map_err = |err| {
+        TrackerError::io(err).context(format!(
+            "failed to read archive directory `{}`",
+            dir.display()
+        ))
+    };
+
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(map_err(err)),
+    };
+
+    let mut versions = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(map_err)?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+
+        if let Some((downloaded_at, filename)) = parse_name(&name) {
+            versions.push(Version {
+                path: entry.path(),
+                filename: filename.to_string(),
+                downloaded_at,
+                size: entry.metadata().map(|metadata| metadata.len()).unwrap_or(0),
+            });
+        }
+    }
+
+    versions.sort_by(|a, b| {
+        a.downloaded_at
+            .cmp(&b.downloaded_at)
+            .then_with(|| a.filename.cmp(&b.filename))
+            .then_with(|| a.path.cmp(&b.path))
+    });
+
+    Ok(versions)
+}
+
+/// Moves `part_path` into `path`, keeping the file that was in `path` in the [`archive_dir()`] of
+/// `story` first if `config.archive` is enabled.
+///
+/// The previous file is only replaced by the rename, so it stays in `path` if that fails. Once
+/// replaced, the oldest copies with the same extension beyond `config.archive_retention` are
+/// removed.
+#[cfg(feature = "downloader")]
+pub(crate) fn replace_file(
+    part_path: &Path,
+    path: &Path,
+    story: &Story,
+    config: &Config,
+) -> errors::Result<()> {
+    let archived = if config.archive {
+        archive_file(path, story, config)?
+    } else {
+        None
+    };
+
+    if let Err(err) = fs::rename(part_path, path) {
+        if let Some(archived) = archived {
+            let _ = fs::remove_file(archived);
+        }
+
+        return Err(TrackerError::io(err).context(format!(
+            "failed to move `{}` into `{}`",
+            part_path.display(),
+            path.display()
+        )));
+    }
+
+    match archived {
+        Some(archived) => remove_old_copies(&archived, story, config),
+        None => Ok(()),
+    }
+}
+
+/// Links the file in `path`, if there is one, into the [`archive_dir()`] of `story`, or copies it
+/// if it can't be linked, returning the path to the archived copy. If a copy with the same date
+/// and name is already there, a counter is added after the date instead of overwriting it.
+#[cfg(feature = "downloader")]
+fn archive_file(path: &Path, story: &Story, config: &Config) -> errors::Result<Option<PathBuf>> {
+    let metadata = match fs::metadata(path) {
+        Ok(metadata) if metadata.is_file() => metadata,
+        Ok(_) => return Ok(None),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => {
+            return Err(TrackerError::io(err)
+                .context(format!("failed to read metadata of `{}`", path.display())))
+        }
+    };
+
+    let downloaded_at: DateTime<Utc> = metadata
+        .modified()
+        .map(DateTime::from)
+        .unwrap_or_else(|_| Utc::now());
+    let filename = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let dir = archive_dir(story, config);
+    fs::create_dir_all(&dir).map_err(|err| {
+        TrackerError::io(err).context(format!("failed to create directory `{}`", dir.display()))
+    })?;
+
+    let date = downloaded_at.format(DATE_FORMAT).to_string();
+    let mut archived = dir.join(format!("{} {}", date, filename));
+    let mut counter = 1;
+    while archived.exists() {
+        archived = dir.join(format!("{}_{} {}", date, counter, filename));
+        counter += 1;
+    }
+    fs::hard_link(path, &archived)
+        .or_else(|_| fs::copy(path, &archived).map(|_| ()))
+        .map_err(|err| {
+            TrackerError::io(err).context(format!(
+                "failed to archive `{}` into `{}`",
+                path.display(),
+                archived.display()
+            ))
+        })?;
+
+    Ok(Some(archived))
+}
+
+/// Removes the oldest copies of `story` with the same extension as `archived` beyond
+/// `config.archive_retention`, if set.
+#[cfg(feature = "downloader")]
+fn remove_old_copies(archived: &Path, story: &Story, config: &Config) -> errors::Result<()> {
+    if config.archive_retention == 0 {
+        return Ok(());
+    }
+
+    let extension = archived.extension();
+    let same_extension: Vec<Version> = versions(story, config)?
+        .into_iter()
+        .filter(|version| Path::new(&version.filename).extension() == extension)
+        .collect();
+    let excess = same_extension
+        .len()
+        .saturating_sub(config.archive_retention);
+
+    for version in &same_extension[..excess] {
+        fs::remove_file(&version.path).map_err(|err| {
+            TrackerError::io(err).context(format!(
+                "failed to remove old copy `{}`",
+                version.path.display()
+            ))
+        })?;
+    }
+
+    Ok(())
+}
+
+#[cfg(all(test, feature = "downloader"))]
+mod test {
+    use super::*;
+
+    use std::time::{Duration, SystemTime};
+
+    use tempdir::TempDir;
+
+    use crate::config::ConfigBuilder;
+
+    fn write_file(path: &Path, contents: &str, age: u64) {
+        fs::write(path, contents).unwrap();
+        fs::File::options()
+            .write(true)
+            .open(path)
+            .and_then(|file| file.set_modified(SystemTime::now() - Duration::from_secs(age)))
+            .unwrap();
+    }
+
+    fn replace(path: &Path, contents: &str, age: u64, story: &Story, config: &Config) {
+        let part_path = path.with_extension("part");
+        write_file(&part_path, contents, age);
+        replace_file(&part_path, path, story, config).unwrap();
+        assert_eq!(fs::read_to_string(path).unwrap(), contents);
+    }
+
+    fn archived_contents(story: &Story, config: &Config) -> Vec<String> {
+        versions(story, config)
+            .unwrap()
+            .iter()
+            .map(|version| fs::read_to_string(&version.path).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn keeps_last_copies() {
+        let tmp_dir = TempDir::new("fft-archive").expect("failed to create temp dir");
+        let config: Config = ConfigBuilder::new()
+            .download_dir(tmp_dir.path().to_string_lossy())
+            .archive(true)
+            .archive_retention(2)
+            .into();
+        let story = Story::default_for_test(165);
+
+        let html = tmp_dir.path().join("A Title.html");
+        let epub = tmp_dir.path().join("A Title.epub");
+
+        assert_eq!(archive_file(&html, &story, &config).unwrap(), None);
+        assert!(versions(&story, &config).unwrap().is_empty());
+
+        for (age, contents) in [
+            (300, "first"),
+            (200, "second"),
+            (100, "third"),
+            (0, "fourth"),
+        ] {
+            replace(&html, contents, age, &story, &config);
+        }
+        replace(&epub, "epub", 150, &story, &config);
+        replace(&epub, "new epub", 0, &story, &config);
+
+        assert_eq!(
+            archived_contents(&story, &config),
+            ["second", "epub", "third"]
+        );
+        let versions = versions(&story, &config).unwrap();
+        assert_eq!(versions[0].filename, "A Title.html");
+        assert_eq!(versions[0].size, 6);
+        assert!(versions[0].path.starts_with(archive_dir(&story, &config)));
+    }
+
+    #[test]
+    fn keeps_copies_with_the_same_date() {
+        let tmp_dir = TempDir::new("fft-archive").expect("failed to create temp dir");
+        let config: Config = ConfigBuilder::new()
+            .download_dir(tmp_dir.path().to_string_lossy())
+            .archive(true)
+            .into();
+        let story = Story::default_for_test(165);
+        let html = tmp_dir.path().join("A Title.html");
+
+        for contents in ["first", "second", "third", "fourth"] {
+            replace(&html, contents, 100, &story, &config);
+        }
+
+        assert_eq!(
+            archived_contents(&story, &config),
+            ["first", "second", "third"]
+        );
+        assert!(versions(&story, &config)
+            .unwrap()
+            .iter()
+            .all(|version| version.filename == "A Title.html"));
+    }
+
+    #[test]
+    fn keeps_file_if_the_rename_fails() {
+        let tmp_dir = TempDir::new("fft-archive").expect("failed to create temp dir");
+        let config: Config = ConfigBuilder::new()
+            .download_dir(tmp_dir.path().to_string_lossy())
+            .archive(true)
+            .into();
+        let story = Story::default_for_test(165);
+        let html = tmp_dir.path().join("A Title.html");
+
+        replace(&html, "first", 100, &story, &config);
+
+        // The partial file is missing, so it can't be moved into place.
+        let missing = tmp_dir.path().join("A Title.html.part");
+        assert!(replace_file(&missing, &html, &story, &config).is_err());
+
+        assert_eq!(fs::read_to_string(&html).unwrap(), "first");
+        assert!(versions(&story, &config).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,331 @@
+//! Local copies of the responses from Fimfiction.
+//!
+//! With [`Config::cache`](crate::Config::cache) enabled, the story data and downloads that
+//! Fimfiction answers with an `ETag` or `Last-Modified` header are kept in [`responses_dir()`].
+//! The next request for them is then made conditional, with `If-None-Match` and
+//! `If-Modified-Since`, and a `304 Not Modified` response is answered with the kept copy instead of
+//! transferring it again.
+//! ```no_run
+//! # use fimfic_tracker::Result;
+//! # fn main() -> Result<()> {
+//! use fimfic_tracker::{cache, Config};
+//! # let config = Config::default();
+//!
+//! let stats = cache::stats(&config)?;
+//! println!("{} responses taking {} bytes", stats.entries, stats.size);
+//!
+//! cache::clear(&config)?;
+//! # Ok(())
+//! # }
+//! ```
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::config::Config;
+use crate::errors::{self, TrackerError};
+
+#[cfg(feature = "downloader")]
+use std::future::Future;
+#[cfg(feature = "downloader")]
+use std::path::Path;
+
+#[cfg(feature = "downloader")]
+use chrono::{DateTime, Utc};
+#[cfg(feature = "downloader")]
+use reqwest::header::{self, HeaderMap};
+#[cfg(feature = "downloader")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "downloader")]
+use sha2::{Digest, Sha256};
+#[cfg(feature = "downloader")]
+use tokio::fs as async_fs;
+#[cfg(feature = "downloader")]
+use url::Url;
+
+/// Extension of the files with the metadata of each kept response, next to its body.
+const METADATA_EXTENSION: &str = "json";
+
+/// The amount of responses kept in the cache and their size, as given by [`stats()`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// The amount of responses.
+    pub entries: usize,
+    /// Size of the responses and their metadata in bytes.
+    pub size: u64,
+}
+
+/// Path to the directory with the kept responses, in the `responses` directory inside of
+/// `config.cache_dir`.
+pub fn responses_dir(config: &Config) -> PathBuf {
+    config.cache_dir.join("responses")
+}
+
+/// The amount of responses kept in [`responses_dir()`] and their size.
+///
+/// # Errors
+///
+/// If the directory exists but can't be read.
+pub fn stats(config: &Config) -> errors::Result<CacheStats> {
+    let dir = responses_dir(config);
+    let map_err = |err| {
+        TrackerError::io(err).context(format!(
+            "failed to read cache directory `{}`",
+            dir.display()
+        ))
+    };
+
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(CacheStats::default()),
+        Err(err) => return Err(map_err(err)),
+    };
+
+    let mut stats = CacheStats::default();
+    for entry in entries {
+        let entry = entry.map_err(map_err)?;
+        if entry.path().extension().and_then(|ext| ext.to_str()) == Some(METADATA_EXTENSION) {
+            stats.entries += 1;
+        }
+        stats.size += entry.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+    }
+
+    Ok(stats)
+}
+
+/// Removes every response kept in [`responses_dir()`], returning the [`stats()`] of what was
+/// removed.
+///
+/// # Errors
+///
+/// If the directory exists but can't be read or removed.
+pub fn clear(config: &Config) -> errors::Result<CacheStats> {
+    let stats = stats(config)?;
+    let dir = responses_dir(config);
+
+    match fs::remove_dir_all(&dir) {
+        Ok(_) => Ok(stats),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(stats),
+        Err(err) => Err(TrackerError::io(err).context(format!(
+            "failed to remove cache directory `{}`",
+            dir.display()
+        ))),
+    }
+}
+
+/// What is kept about a response besides its body.
+#[cfg(feature = "downloader")]
+#[derive(Debug, Serialize, Deserialize)]
+struct Metadata {
+    url: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    stored_at: DateTime<Utc>,
+}
+
+/// A response kept in the cache, as given by [`lookup()`].
+#[cfg(feature = "downloader")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CachedResponse {
+    /// Path to the kept body.
+    pub body: PathBuf,
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+#[cfg(feature = "downloader")]
+impl CachedResponse {
+    /// The headers that make a request conditional on the response having changed since it was
+    /// kept.
+    pub fn conditional_headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+
+        if let Some(value) = self.etag.as_ref().and_then(|etag| etag.parse().ok()) {
+            headers.insert(header::IF_NONE_MATCH, value);
+        }
+
+        if let Some(value) = self
+            .last_modified
+            .as_ref()
+            .and_then(|date| date.parse().ok())
+        {
+            headers.insert(header::IF_MODIFIED_SINCE, value);
+        }
+
+        headers
+    }
+}
+
+/// Paths to the body and metadata kept for `url`, named after the SHA-256 hash of it.
+#[cfg(feature = "downloader")]
+fn entry_paths(url: &Url, config: &Config) -> (PathBuf, PathBuf) {
+    let key = format!("{:x}", Sha256::digest(url.as_str()));
+    let body = responses_dir(config).join(&key);
+    let metadata = body.with_extension(METADATA_EXTENSION);
+    (body, metadata)
+}
+
+/// The response kept for `url`, if there is a complete one.
+#[cfg(feature = "downloader")]
+pub(crate) async fn lookup(url: &Url, config: &Config) -> Option<CachedResponse> {
+    let (body, metadata) = entry_paths(url, config);
+
+    let metadata: Metadata = serde_json::from_slice(&async_fs::read(metadata).await.ok()?).ok()?;
+    let is_file = async_fs::metadata(&body)
+        .await
+        .map(|metadata| metadata.is_file())
+        .unwrap_or(false);
+    if metadata.url != url.as_str() || !is_file {
+        return None;
+    }
+
+    Some(CachedResponse {
+        body,
+        etag: metadata.etag,
+        last_modified: metadata.last_modified,
+    })
+}
+
+/// Keeps `body` as the response to `url`, if its `headers` allow the next request to be
+/// conditional, replacing any previous one.
+///
+/// # Errors
+///
+/// If the body or its metadata can't be written into [`responses_dir()`].
+#[cfg(feature = "downloader")]
+pub(crate) async fn store(
+    url: &Url,
+    headers: &HeaderMap,
+    body: &[u8],
+    config: &Config,
+) -> errors::Result<()> {
+    store_with(url, headers, config, |path| async_fs::write(path, body)).await
+}
+
+/// Keeps the file in `body` as the response to `url`, as in [`store()`].
+#[cfg(feature = "downloader")]
+pub(crate) async fn store_file(
+    url: &Url,
+    headers: &HeaderMap,
+    body: &Path,
+    config: &Config,
+) -> errors::Result<()> {
+    store_with(url, headers, config, |path| async move {
+        async_fs::copy(body, path).await.map(|_| ())
+    })
+    .await
+}
+
+#[cfg(feature = "downloader")]
+async fn store_with<F, Fut>(
+    url: &Url,
+    headers: &HeaderMap,
+    config: &Config,
+    write_body: F,
+) -> errors::Result<()>
+where
+    F: FnOnce(PathBuf) -> Fut,
+    Fut: Future<Output = io::Result<()>>,
+{
+    let header_value = |name| {
+        headers
+            .get(name)
+            .and_then(|value: &header::HeaderValue| value.to_str().ok())
+            .map(|value| value.to_string())
+    };
+
+    let metadata = Metadata {
+        url: url.to_string(),
+        etag: header_value(header::ETAG),
+        last_modified: header_value(header::LAST_MODIFIED),
+        stored_at: Utc::now(),
+    };
+
+    if metadata.etag.is_none() && metadata.last_modified.is_none() {
+        return Ok(());
+    }
+
+    let dir = responses_dir(config);
+    async_fs::create_dir_all(&dir).await.map_err(|err| {
+        TrackerError::io(err).context(format!("failed to create directory `{}`", dir.display()))
+    })?;
+
+    let (body_path, metadata_path) = entry_paths(url, config);
+    let map_err = |err| {
+        TrackerError::io(err).context(format!(
+            "failed to keep the response to `{}` in the cache",
+            url
+        ))
+    };
+
+    // Without its metadata the entry is ignored, so a body that fails to be written halfway is
+    // never taken as complete.
+    match async_fs::remove_file(&metadata_path).await {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(map_err(err)),
+        _ => {}
+    }
+
+    write_body(body_path).await.map_err(map_err)?;
+    async_fs::write(
+        &metadata_path,
+        serde_json::to_vec(&metadata).expect("cache metadata should be serializable"),
+    )
+    .await
+    .map_err(map_err)
+}
+
+#[cfg(all(test, feature = "downloader"))]
+mod test {
+    use super::*;
+
+    use reqwest::header::HeaderValue;
+    use tempdir::TempDir;
+
+    use crate::config::ConfigBuilder;
+
+    #[tokio::test]
+    async fn keeps_responses() {
+        let tmp_dir = TempDir::new("fft-cache").expect("failed to create temp dir");
+        let config: Config = ConfigBuilder::new()
+            .cache_dir(tmp_dir.path().to_string_lossy())
+            .into();
+        let url: Url = "https://www.fimfiction.net/story/download/165/html"
+            .parse()
+            .unwrap();
+        let body = tmp_dir.path().join("A Title.html");
+        fs::write(&body, "content").unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ETAG, HeaderValue::from_static("\"abc\""));
+
+        assert_eq!(stats(&config).unwrap(), CacheStats::default());
+
+        store_file(&url, &HeaderMap::new(), &body, &config)
+            .await
+            .unwrap();
+        assert_eq!(lookup(&url, &config).await, None);
+
+        store(&url, &headers, b"previous", &config).await.unwrap();
+        store_file(&url, &headers, &body, &config).await.unwrap();
+
+        let cached = lookup(&url, &config)
+            .await
+            .expect("response should be kept");
+        assert_eq!(fs::read_to_string(&cached.body).unwrap(), "content");
+        let conditional = cached.conditional_headers();
+        assert_eq!(conditional[header::IF_NONE_MATCH], "\"abc\"");
+        assert!(!conditional.contains_key(header::IF_MODIFIED_SINCE));
+
+        let other_url: Url = "https://www.fimfiction.net/story/download/165/epub"
+            .parse()
+            .unwrap();
+        assert_eq!(lookup(&other_url, &config).await, None);
+
+        let kept = stats(&config).unwrap();
+        assert_eq!(kept.entries, 1);
+        assert!(kept.size > 7);
+
+        assert_eq!(clear(&config).unwrap(), kept);
+        assert_eq!(lookup(&url, &config).await, None);
+        assert_eq!(stats(&config).unwrap(), CacheStats::default());
+    }
+}
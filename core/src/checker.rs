@@ -0,0 +1,709 @@
+//! Update checking of tracked stories.
+//!
+//! It takes care of comparing the tracked data of a story with its current state on Fimfiction,
+//! deciding if the update found is relevant according to the configured [`SensibilityLevel`] and
+//! keeping track of which stories should be downloaded.
+//!
+//! With the `downloader` feature enabled, [`check_updates()`] and [`async_check_updates()`]
+//! request the current data of each story and return a [`CheckReport`] with the result, where the
+//! stories that fail to be checked can be recorded as [`StoryFailure`]s instead of stopping the
+//! whole check. The amount of requests in-flight at the same time and their rate are set by
+//! [`Config::max_concurrent_requests`](crate::Config::max_concurrent_requests) and
+//! [`Config::requests_per_second`](crate::Config::requests_per_second).
+//! ```no_run
+//! # use fimfic_tracker::Result;
+//! # fn main() -> Result<()> {
+//! use fimfic_tracker::{checker, Config, StoryData};
+//! use fimfic_tracker::downloader::{BlockingRequester, SilentListener};
+//! # let config = Config::default();
+//!
+//! let mut story_data = StoryData::new(&config.tracker_file);
+//! story_data.load()?;
+//!
+//! let requester = BlockingRequester::new(config.clone(), SilentListener)?;
+//! let report = checker::check_updates(&requester, story_data.values(), &config, false)?;
+//!
+//! for check in report.changes() {
+//!     println!("{} has changed: {:?}", check.before.title, check.update);
+//! }
+//!
+//! // Keep the data of the stories that won't be downloaded up-to-date.
+//! report.apply_ignored(&mut story_data);
+//!
+//! for download in report.downloads(false) {
+//!     requester.download(download.story())?;
+//!
+//!     if let checker::StoryDownload::Update(story) = download {
+//!         story_data.insert(story.id, story);
+//!     }
+//! }
+//!
+//! story_data.save()?;
+//! # Ok(())
+//! # }
+//! ```
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+
+use crate::config::SensibilityLevel;
+use crate::errors::{self, TrackerError};
+use crate::story::{Id, Story, StoryUpdate};
+use crate::utils::StoryData;
+
+#[cfg(feature = "downloader")]
+use crate::config::Config;
+#[cfg(feature = "downloader")]
+use crate::downloader::{AsyncRequester, BlockingRequester, ProgressListener};
+#[cfg(feature = "downloader")]
+use crate::StoryResponse;
+
+/// Result of comparing the tracked data of a story with its current data.
+#[derive(Debug, Clone)]
+pub struct StoryCheck {
+    /// The story as it is in the track data.
+    pub before: Story,
+    /// The story as it currently is on Fimfiction.
+    pub after: Story,
+    /// The update found by [`Story::compare_to()`], if any.
+    pub update: Option<StoryUpdate>,
+    relevant: bool,
+    skip_download: bool,
+}
+
+impl StoryCheck {
+    /// Constructs a new [`StoryCheck`] from the comparison of `before` to `after`, where the
+    /// relevance of the update is decided by `sensibility_level`.
+    ///
+    /// What is tracked of `before` besides its data is kept in `after`, as in
+    /// [`Story::keep_tracking_data()`], and the story is never considered for download if its
+    /// [overrides](crate::story::StoryOverrides) say so.
+    ///
+    /// # Errors
+    ///
+    /// If the ID of `after` isn't the same as of `before`.
+    pub fn new(
+        before: Story,
+        mut after: Story,
+        sensibility_level: SensibilityLevel,
+    ) -> errors::Result<Self> {
+        let update = before.compare_to(&after)?;
+        let relevant = update
+            .as_ref()
+            .map(|update| update.is_relevant(sensibility_level))
+            .unwrap_or(false);
+
+        after.keep_tracking_data(&before);
+        let skip_download = before.overrides.skip_download();
+
+        Ok(StoryCheck {
+            before,
+            after,
+            update,
+            relevant,
+            skip_download,
+        })
+    }
+
+    /// The ID of the checked story.
+    pub fn id(&self) -> Id {
+        self.before.id
+    }
+
+    /// Whether the story has changed its title.
+    pub fn title_changed(&self) -> bool {
+        self.before.title != self.after.title
+    }
+
+    /// Whether the story has changed its author.
+    pub fn author_changed(&self) -> bool {
+        self.before.author != self.after.author
+    }
+
+    /// Whether the story has changed its completion status.
+    pub fn status_changed(&self) -> bool {
+        self.before.status != self.after.status
+    }
+
+    /// Whether the story has changed its title, author or completion status.
+    pub fn has_metadata_changes(&self) -> bool {
+        self.title_changed() || self.author_changed() || self.status_changed()
+    }
+
+    /// Whether the story has an update or any change in its metadata.
+    pub fn has_changes(&self) -> bool {
+        self.update.is_some() || self.has_metadata_changes()
+    }
+
+    /// Whether the story has an update that isn't relevant enough to be downloaded.
+    pub fn is_ignored(&self) -> bool {
+        self.update.is_some() && !self.relevant
+    }
+
+    /// Whether the story has an update relevant enough to be downloaded, but it's set to skip
+    /// its downloads.
+    pub fn is_skipped(&self) -> bool {
+        self.relevant && self.skip_download
+    }
+
+    /// Whether the story has an update relevant enough to be downloaded and isn't set to skip
+    /// its downloads.
+    pub fn should_download(&self) -> bool {
+        self.relevant && !self.skip_download
+    }
+}
+
+/// A story that is meant to be downloaded, as given by [`CheckReport::downloads()`].
+#[derive(Debug, Clone)]
+pub enum StoryDownload {
+    /// Story with a relevant update, to be stored into the track data once it is downloaded.
+    Update(Story),
+    /// Story without a relevant update whose download was forced.
+    Forced(Story),
+}
+
+impl StoryDownload {
+    /// The story to download.
+    pub fn story(&self) -> &Story {
+        match self {
+            StoryDownload::Update(story) | StoryDownload::Forced(story) => story,
+        }
+    }
+}
+
+/// The step in which a story failed, as recorded in [`StoryFailure`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureStage {
+    /// Requesting its current data or comparing it with the tracked one.
+    Check,
+    /// Downloading it.
+    Download,
+}
+
+impl fmt::Display for FailureStage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FailureStage::Check => write!(f, "check"),
+            FailureStage::Download => write!(f, "download"),
+        }
+    }
+}
+
+/// A story that failed to be checked or downloaded.
+#[derive(Debug)]
+pub struct StoryFailure {
+    /// The story as it is in the track data.
+    pub story: Story,
+    /// The step in which it failed.
+    pub stage: FailureStage,
+    /// The error it failed with.
+    pub error: TrackerError,
+}
+
+/// Collection of [`StoryCheck`]s, in the order they were made, along with the
+/// [`StoryFailure`]s of the stories that couldn't be checked or downloaded.
+#[derive(Debug, Default)]
+pub struct CheckReport {
+    checks: Vec<StoryCheck>,
+    failures: Vec<StoryFailure>,
+}
+
+impl CheckReport {
+    /// Constructs a new empty [`CheckReport`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `check` to the report.
+    pub fn push(&mut self, check: StoryCheck) {
+        self.checks.push(check);
+    }
+
+    /// Records that `story` failed in `stage` with `error`.
+    pub fn push_failure(&mut self, story: &Story, stage: FailureStage, error: TrackerError) {
+        self.failures.push(StoryFailure {
+            story: story.clone(),
+            stage,
+            error,
+        });
+    }
+
+    /// Every failure recorded, in the order they happened.
+    pub fn failures(&self) -> impl Iterator<Item = &StoryFailure> {
+        self.failures.iter()
+    }
+
+    /// Whether `id` has a recorded failure.
+    pub fn has_failed(&self, id: Id) -> bool {
+        self.failures.iter().any(|failure| failure.story.id == id)
+    }
+
+    /// Every story that was checked.
+    pub fn checked(&self) -> impl Iterator<Item = &StoryCheck> {
+        self.checks.iter()
+    }
+
+    /// Every story that has an update or any change in its metadata.
+    pub fn changes(&self) -> impl Iterator<Item = &StoryCheck> {
+        self.checks.iter().filter(|check| check.has_changes())
+    }
+
+    /// Every story that has an update not relevant enough to be downloaded.
+    pub fn ignored_updates(&self) -> impl Iterator<Item = &StoryCheck> {
+        self.checks.iter().filter(|check| check.is_ignored())
+    }
+
+    /// Every story that has an update relevant enough to be downloaded.
+    pub fn to_download(&self) -> impl Iterator<Item = &StoryCheck> {
+        self.checks.iter().filter(|check| check.should_download())
+    }
+
+    /// Inserts into `story_data` the current data of every story that has changes but isn't
+    /// going to be downloaded, so ignored updates aren't reported again on the next check.
+    pub fn apply_ignored(&self, story_data: &mut StoryData) {
+        for check in self.changes().filter(|check| !check.should_download()) {
+            story_data.insert(check.id(), check.after.clone());
+        }
+    }
+
+    /// Inserts into `story_data` the current data of every story that has changes, be it an
+    /// update or not, so none of them are reported again on the next check.
+    pub fn apply_changes(&self, story_data: &mut StoryData) {
+        for check in self.changes() {
+            story_data.insert(check.id(), check.after.clone());
+        }
+    }
+
+    /// Sets `checked_at` as the last time that the stories of every check were checked, for
+    /// those in `story_data`.
+    pub fn apply_checked_at(&self, story_data: &mut StoryData, checked_at: DateTime<Utc>) {
+        for check in &self.checks {
+            if let Some(story) = story_data.get_mut(&check.id()) {
+                story.last_checked_at = Some(checked_at);
+            }
+        }
+    }
+
+    /// The stories to download, where if `force` is:
+    /// - `false`, only includes those with a relevant update.
+    /// - `true`, includes every checked story.
+    ///
+    /// Stories set to skip their downloads are never included.
+    pub fn downloads(&self, force: bool) -> Vec<StoryDownload> {
+        self.checks
+            .iter()
+            .filter(|check| !check.skip_download && (force || check.should_download()))
+            .map(|check| {
+                if check.should_download() {
+                    StoryDownload::Update(check.after.clone())
+                } else if check.has_changes() {
+                    StoryDownload::Forced(check.after.clone())
+                } else {
+                    StoryDownload::Forced(check.before.clone())
+                }
+            })
+            .collect()
+    }
+}
+
+/// Tells `listener` that `check` was made, and whether the story was skipped.
+#[cfg(feature = "downloader")]
+fn notify_check<P>(listener: &P, check: &StoryCheck)
+where
+    P: ProgressListener,
+{
+    listener.story_checked(&check.before, check.update.as_ref());
+    if check.is_skipped() {
+        listener.story_skipped(&check.before);
+    }
+}
+
+/// Requests the current data of `story` and compares it with the given one.
+#[cfg(feature = "downloader")]
+pub async fn async_check_story<P>(
+    requester: &AsyncRequester<P>,
+    story: &Story,
+    config: &Config,
+) -> errors::Result<StoryCheck>
+where
+    P: ProgressListener,
+{
+    requester.listener().before_story_check(story);
+    let updated_story: Story = requester.get_story_response(story.id).await?.into();
+    let check = StoryCheck::new(
+        story.clone(),
+        updated_story,
+        config.for_story(story).sensibility_level,
+    )?;
+
+    notify_check(requester.listener(), &check);
+    Ok(check)
+}
+
+/// Blocking version of [`async_check_story()`].
+#[cfg(feature = "downloader")]
+pub fn check_story<P>(
+    requester: &BlockingRequester<P>,
+    story: &Story,
+    config: &Config,
+) -> errors::Result<StoryCheck>
+where
+    P: ProgressListener,
+{
+    requester.listener().before_story_check(story);
+    let updated_story: Story = requester.get_story_response(story.id)?.into();
+    let check = StoryCheck::new(
+        story.clone(),
+        updated_story,
+        config.for_story(story).sensibility_level,
+    )?;
+
+    notify_check(requester.listener(), &check);
+    Ok(check)
+}
+
+/// Compares every story in `stories` with the result of requesting its current data, in order,
+/// telling `listener` about each check.
+///
+/// If `keep_going`, the stories that fail are recorded in the report instead of returning the
+/// first error.
+#[cfg(feature = "downloader")]
+fn report_from_responses<P>(
+    stories: Vec<&Story>,
+    responses: Vec<(Id, errors::Result<StoryResponse>)>,
+    config: &Config,
+    listener: &P,
+    keep_going: bool,
+) -> errors::Result<CheckReport>
+where
+    P: ProgressListener,
+{
+    let mut report = CheckReport::new();
+
+    for (story, (_, response)) in stories.into_iter().zip(responses) {
+        let result = response.and_then(|response| {
+            StoryCheck::new(
+                story.clone(),
+                response.into(),
+                config.for_story(story).sensibility_level,
+            )
+        });
+
+        match result {
+            Ok(check) => {
+                notify_check(listener, &check);
+                report.push(check);
+            }
+            Err(err) if keep_going => report.push_failure(story, FailureStage::Check, err),
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(report)
+}
+
+/// Checks every story in `stories` for updates, requesting their current data concurrently
+/// with [`AsyncRequester::get_story_responses()`].
+///
+/// If `keep_going`, the stories that fail to be checked are recorded in the report with
+/// [`FailureStage::Check`] and the rest are still checked.
+///
+/// # Errors
+///
+/// Unless `keep_going`, on the first story, in the order of `stories`, that fails to be checked.
+#[cfg(feature = "downloader")]
+pub async fn async_check_updates<'a, P, I>(
+    requester: &AsyncRequester<P>,
+    stories: I,
+    config: &Config,
+    keep_going: bool,
+) -> errors::Result<CheckReport>
+where
+    P: ProgressListener,
+    I: IntoIterator<Item = &'a Story>,
+{
+    let stories: Vec<&Story> = stories.into_iter().collect();
+    for story in stories.iter() {
+        requester.listener().before_story_check(story);
+    }
+    let responses = requester
+        .get_story_responses(stories.iter().map(|story| story.id))
+        .await;
+
+    report_from_responses(stories, responses, config, requester.listener(), keep_going)
+}
+
+/// Blocking version of [`async_check_updates()`].
+#[cfg(feature = "downloader")]
+pub fn check_updates<'a, P, I>(
+    requester: &BlockingRequester<P>,
+    stories: I,
+    config: &Config,
+    keep_going: bool,
+) -> errors::Result<CheckReport>
+where
+    P: ProgressListener,
+    I: IntoIterator<Item = &'a Story>,
+{
+    let stories: Vec<&Story> = stories.into_iter().collect();
+    for story in stories.iter() {
+        requester.listener().before_story_check(story);
+    }
+    let responses = requester.get_story_responses(stories.iter().map(|story| story.id));
+
+    report_from_responses(stories, responses, config, requester.listener(), keep_going)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use chrono::TimeZone;
+
+    use crate::StoryStatus;
+
+    fn story(chapter_count: u64, words: u64, timestamp: i64) -> Story {
+        Story {
+            chapter_count,
+            words,
+            update_datetime: Utc.timestamp_opt(timestamp, 0).single().unwrap(),
+            ..Story::default_for_test(1)
+        }
+    }
+
+    macro_rules! check {
+        ($level:ident: $before:expr => $after:expr) => {
+            StoryCheck::new($before, $after, SensibilityLevel::$level)
+                .expect("stories should have the same ID")
+        };
+    }
+
+    #[test]
+    fn update_relevance() {
+        let base = story(5, 1000, 100);
+
+        let check = check!(OnlyChapters: base.clone() => story(6, 1200, 200));
+        assert!(check.should_download());
+        assert!(!check.is_ignored());
+
+        let check = check!(OnlyChapters: base.clone() => story(5, 1200, 200));
+        assert!(!check.should_download());
+        assert!(check.is_ignored());
+
+        let check = check!(IncludeWords: base.clone() => story(5, 1200, 200));
+        assert!(check.should_download());
+
+        let check = check!(IncludeWords: base.clone() => story(5, 1000, 200));
+        assert!(check.is_ignored());
+
+        let check = check!(Anything: base.clone() => story(5, 1000, 200));
+        assert!(check.should_download());
+
+        let check = check!(Anything: base.clone() => base.clone());
+        assert!(!check.has_changes());
+        assert!(!check.should_download());
+        assert!(!check.is_ignored());
+    }
+
+    #[test]
+    fn metadata_changes() {
+        let base = story(5, 1000, 100);
+
+        let mut renamed = base.clone();
+        renamed.title = "Another Title".into();
+        let check = check!(OnlyChapters: base.clone() => renamed);
+        assert!(check.title_changed());
+        assert!(check.has_metadata_changes());
+        assert!(check.has_changes());
+        assert!(!check.should_download());
+
+        let mut completed = base.clone();
+        completed.status = StoryStatus::Complete;
+        let check = check!(OnlyChapters: base.clone() => completed);
+        assert!(check.status_changed());
+        assert!(!check.author_changed());
+        assert!(check.has_changes());
+    }
+
+    #[test]
+    fn report_downloads() {
+        let mut report = CheckReport::new();
+
+        let mut updated = story(5, 1000, 100);
+        updated.id = 2;
+        let mut after = updated.clone();
+        after.chapter_count = 6;
+        report.push(check!(OnlyChapters: updated => after));
+
+        let mut ignored = story(5, 1000, 100);
+        ignored.id = 3;
+        let mut after = ignored.clone();
+        after.words = 1500;
+        report.push(check!(OnlyChapters: ignored => after));
+
+        report.push(check!(OnlyChapters: story(5, 1000, 100) => story(5, 1000, 100)));
+
+        assert_eq!(report.checked().count(), 3);
+        assert_eq!(report.changes().count(), 2);
+        assert_eq!(report.ignored_updates().count(), 1);
+        assert_eq!(report.to_download().count(), 1);
+
+        let downloads = report.downloads(false);
+        assert_eq!(downloads.len(), 1);
+        assert!(matches!(&downloads[0], StoryDownload::Update(story) if story.id == 2));
+
+        let downloads = report.downloads(true);
+        assert_eq!(downloads.len(), 3);
+        assert!(matches!(&downloads[1], StoryDownload::Forced(story) if story.words == 1500));
+
+        let mut story_data = StoryData::new("track-data.json");
+        report.apply_ignored(&mut story_data);
+        assert_eq!(story_data.len(), 1);
+        assert_eq!(story_data.get(&3).map(|story| story.words), Some(1500));
+
+        let mut story_data = StoryData::new("track-data.json");
+        report.apply_changes(&mut story_data);
+        assert_eq!(story_data.len(), 2);
+        assert_eq!(story_data.get(&2).map(|story| story.chapter_count), Some(6));
+
+        let checked_at = Utc::now();
+        report.apply_checked_at(&mut story_data, checked_at);
+        assert_eq!(story_data.len(), 2);
+        assert!(story_data
+            .values()
+            .all(|story| story.last_checked_at == Some(checked_at)));
+    }
+
+    #[test]
+    fn skipped_downloads() {
+        let mut base = story(5, 1000, 100);
+        base.overrides.skip_download = Some(true);
+        base.overrides.exec = Some("notify-send $TITLE".into());
+        base.added_at = Some(Utc.timestamp_opt(50, 0).single().unwrap());
+
+        let check = check!(OnlyChapters: base.clone() => story(6, 1000, 100));
+        assert!(check.is_skipped());
+        assert!(!check.should_download());
+        assert!(!check.is_ignored());
+        assert_eq!(check.after.overrides, base.overrides);
+        assert_eq!(check.after.added_at, base.added_at);
+
+        let mut report = CheckReport::new();
+        report.push(check);
+        assert!(report.downloads(true).is_empty());
+
+        let mut story_data = StoryData::new("track-data.json");
+        report.apply_ignored(&mut story_data);
+        assert_eq!(story_data.get(&1).map(|story| story.chapter_count), Some(6));
+    }
+
+    #[test]
+    fn report_failures() {
+        let mut report = CheckReport::new();
+
+        let mut failed = story(5, 1000, 100);
+        failed.id = 2;
+        report.push_failure(
+            &failed,
+            FailureStage::Check,
+            TrackerError::custom("story was deleted"),
+        );
+        report.push(check!(OnlyChapters: story(5, 1000, 100) => story(6, 1000, 100)));
+
+        assert!(report.has_failed(2));
+        assert!(!report.has_failed(1));
+        assert_eq!(report.failures().count(), 1);
+
+        // Stories that failed to be checked are never downloaded.
+        let downloads = report.downloads(true);
+        assert_eq!(downloads.len(), 1);
+        assert_eq!(downloads[0].story().id, 1);
+    }
+
+    #[cfg(feature = "downloader")]
+    #[test]
+    fn check_notifications() {
+        use std::cell::RefCell;
+
+        #[derive(Default)]
+        struct RecordingListener(RefCell<Vec<String>>);
+
+        impl ProgressListener for RecordingListener {
+            fn download_progress(&self, _progress: &crate::downloader::DownloadProgress) {}
+            fn successfull_client_download(&self, _story: &Story) {}
+            fn before_execute_command(&self, _story: &Story) {}
+            fn successfull_command_execution(&self, _story: &Story) {}
+
+            fn story_checked(&self, story: &Story, update: Option<&StoryUpdate>) {
+                self.0
+                    .borrow_mut()
+                    .push(format!("checked {} {}", story.id, update.is_some()));
+            }
+
+            fn story_skipped(&self, story: &Story) {
+                self.0.borrow_mut().push(format!("skipped {}", story.id));
+            }
+        }
+
+        let listener = RecordingListener::default();
+        notify_check(
+            &listener,
+            &check!(OnlyChapters: story(5, 1000, 100) => story(5, 1000, 100)),
+        );
+
+        let mut skipped = story(5, 1000, 100);
+        skipped.overrides.skip_download = Some(true);
+        notify_check(
+            &listener,
+            &check!(OnlyChapters: skipped => story(6, 1000, 100)),
+        );
+
+        assert_eq!(
+            listener.0.into_inner(),
+            ["checked 1 false", "checked 1 true", "skipped 1"]
+        );
+    }
+
+    #[cfg(feature = "downloader")]
+    #[test]
+    fn failed_responses() {
+        use crate::downloader::SilentListener;
+
+        let first = story(5, 1000, 100);
+        let mut second = story(5, 1000, 100);
+        second.id = 2;
+        let responses = || {
+            vec![
+                (1, Err(TrackerError::custom("story was deleted"))),
+                (2, Err(TrackerError::custom("request timed out"))),
+            ]
+        };
+        let config = Config::default();
+
+        assert!(report_from_responses(
+            vec![&first, &second],
+            responses(),
+            &config,
+            &SilentListener,
+            false
+        )
+        .is_err());
+
+        let report = report_from_responses(
+            vec![&first, &second],
+            responses(),
+            &config,
+            &SilentListener,
+            true,
+        )
+        .expect("failures should be recorded in the report");
+        assert_eq!(report.checked().count(), 0);
+        assert_eq!(
+            report
+                .failures()
+                .map(|failure| (failure.story.id, failure.stage))
+                .collect::<Vec<_>>(),
+            [(1, FailureStage::Check), (2, FailureStage::Check)]
+        );
+    }
+}
@@ -1,7 +1,7 @@
-use std::fmt;
+use std::{cmp::Ordering, fmt, str::FromStr};
 
-use serde::de::{self, Unexpected, Visitor};
-use serde::{Deserialize, Deserializer};
+use serde::de::{self, SeqAccess, Unexpected, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 /// Represents the available story formats that Fimfiction provides.
 ///
@@ -13,6 +13,13 @@ use serde::{Deserialize, Deserializer};
 /// assert_eq!(DownloadFormat::TXT.to_string(), "txt");
 /// ```
 ///
+/// It can also be parsed from them:
+/// ```
+/// # use fimfic_tracker::DownloadFormat;
+/// assert_eq!("epub".parse::<DownloadFormat>(), Ok(DownloadFormat::EPUB));
+/// assert!("pdf".parse::<DownloadFormat>().is_err());
+/// ```
+///
 /// Used for [`ConfigBuilder`](crate::ConfigBuilder) and [`Config`](crate::Config).
 #[derive(Clone, Copy, Debug)]
 pub enum DownloadFormat {
@@ -30,6 +37,20 @@ impl PartialEq for DownloadFormat {
     }
 }
 
+impl Eq for DownloadFormat {}
+
+impl PartialOrd for DownloadFormat {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for DownloadFormat {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (*self as u8).cmp(&(*other as u8))
+    }
+}
+
 impl fmt::Display for DownloadFormat {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -40,6 +61,25 @@ impl fmt::Display for DownloadFormat {
     }
 }
 
+impl FromStr for DownloadFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        FormatVisitor
+            .visit_str(value)
+            .map_err(|err: de::value::Error| err.to_string())
+    }
+}
+
+impl Serialize for DownloadFormat {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
 struct FormatVisitor;
 
 impl<'de> Visitor<'de> for FormatVisitor {
@@ -78,3 +118,46 @@ impl<'de> Deserialize<'de> for DownloadFormat {
         deserializer.deserialize_any(FormatVisitor)
     }
 }
+
+struct FormatsVisitor;
+
+impl<'de> Visitor<'de> for FormatsVisitor {
+    type Value = Vec<DownloadFormat>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a download format, a list of them or a comma-separated string of them")
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        value
+            .split(',')
+            .map(|format| FormatVisitor.visit_str(format.trim()))
+            .collect()
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut formats = Vec::with_capacity(seq.size_hint().unwrap_or(1));
+        while let Some(format) = seq.next_element()? {
+            formats.push(format);
+        }
+
+        Ok(formats)
+    }
+}
+
+/// Deserializes the `download_format` option of [`ConfigBuilder`](crate::ConfigBuilder), which
+/// can be a single format, a list of them or a comma-separated string of them.
+pub(crate) fn deserialize_formats<'de, D>(
+    deserializer: D,
+) -> Result<Option<Vec<DownloadFormat>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserializer.deserialize_any(FormatsVisitor).map(Some)
+}
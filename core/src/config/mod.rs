@@ -1,21 +1,35 @@
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 
 use directories::UserDirs;
 use serde::Deserialize;
+use url::Url;
 
 mod format;
 mod sensibility;
 
 use crate::errors::{self, ConfigSource, TrackerError};
+use crate::story::Story;
 use crate::utils::{
-    async_read_to_string, default_user_config_file, default_user_tracker_file, read_to_string,
+    async_read_to_string, default_user_cache_dir, default_user_config_file,
+    default_user_tracker_file, read_to_string,
 };
+pub(crate) use format::deserialize_formats;
 pub use format::DownloadFormat;
 pub use sensibility::SensibilityLevel;
 
 /// Default prefix for configuration by environment variables.
 pub const DEFAULT_ENVIRONMENT_PREFIX: &str = "FFT";
 
+/// Default value of [`Config::filename_template`].
+pub(crate) const DEFAULT_FILENAME_TEMPLATE: &str = "$TITLE.$FORMAT";
+
+/// Default value of [`Config::base_url`].
+pub const DEFAULT_BASE_URL: &str = "https://www.fimfiction.net/";
+
+/// Default value of [`Config::user_agent`].
+pub const DEFAULT_USER_AGENT: &str = concat!("fimfic-tracker/", env!("CARGO_PKG_VERSION"));
+
 /// Used to construct [`Config`].
 ///
 /// # Example
@@ -49,11 +63,29 @@ pub const DEFAULT_ENVIRONMENT_PREFIX: &str = "FFT";
 pub struct ConfigBuilder {
     download_dir: Option<String>,
     tracker_file: Option<String>,
-    download_format: Option<DownloadFormat>,
+    #[serde(default, deserialize_with = "format::deserialize_formats")]
+    download_format: Option<Vec<DownloadFormat>>,
     download_delay: Option<u64>,
+    filename_template: Option<String>,
     sensibility_level: Option<SensibilityLevel>,
     exec: Option<String>,
     quiet: Option<bool>,
+    archive: Option<bool>,
+    archive_retention: Option<usize>,
+    max_concurrent_requests: Option<usize>,
+    requests_per_second: Option<f64>,
+    max_attempts: Option<u32>,
+    retry_delay: Option<f64>,
+    retry_jitter: Option<bool>,
+    request_timeout: Option<u64>,
+    connect_timeout: Option<u64>,
+    user_agent: Option<String>,
+    proxy: Option<String>,
+    headers: Option<BTreeMap<String, String>>,
+    base_url: Option<Url>,
+    cache: Option<bool>,
+    cache_dir: Option<String>,
+    offline: Option<bool>,
 }
 
 macro_rules! default_config_file {
@@ -81,9 +113,26 @@ impl ConfigBuilder {
             tracker_file: None,
             download_format: None,
             download_delay: None,
+            filename_template: None,
             sensibility_level: None,
             exec: None,
             quiet: None,
+            archive: None,
+            archive_retention: None,
+            max_concurrent_requests: None,
+            requests_per_second: None,
+            max_attempts: None,
+            retry_delay: None,
+            retry_jitter: None,
+            request_timeout: None,
+            connect_timeout: None,
+            user_agent: None,
+            proxy: None,
+            headers: None,
+            base_url: None,
+            cache: None,
+            cache_dir: None,
+            offline: None,
         }
     }
 
@@ -105,9 +154,18 @@ impl ConfigBuilder {
         self
     }
 
-    /// Sets the value of `download_format`.
+    /// Sets the value of `download_format` to a single format.
     pub fn download_format(mut self, format: DownloadFormat) -> Self {
-        self.download_format = Some(format);
+        self.download_format = Some(vec![format]);
+        self
+    }
+
+    /// Sets the value of `download_format` to several formats.
+    pub fn download_formats<I>(mut self, formats: I) -> Self
+    where
+        I: IntoIterator<Item = DownloadFormat>,
+    {
+        self.download_format = Some(formats.into_iter().collect());
         self
     }
 
@@ -117,6 +175,15 @@ impl ConfigBuilder {
         self
     }
 
+    /// Sets the value of `filename_template`.
+    pub fn filename_template<T>(mut self, template: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.filename_template = Some(template.into());
+        self
+    }
+
     /// Sets the value of `sensibility_level`.
     pub fn sensibility_level(mut self, sensibility: SensibilityLevel) -> Self {
         self.sensibility_level = Some(sensibility);
@@ -138,6 +205,121 @@ impl ConfigBuilder {
         self
     }
 
+    /// Sets the value of `archive`.
+    pub fn archive(mut self, archive: bool) -> Self {
+        self.archive = Some(archive);
+        self
+    }
+
+    /// Sets the value of `archive_retention`.
+    pub fn archive_retention(mut self, retention: usize) -> Self {
+        self.archive_retention = Some(retention);
+        self
+    }
+
+    /// Sets the value of `max_concurrent_requests`.
+    pub fn max_concurrent_requests(mut self, max: usize) -> Self {
+        self.max_concurrent_requests = Some(max);
+        self
+    }
+
+    /// Sets the value of `requests_per_second`.
+    pub fn requests_per_second(mut self, rate: f64) -> Self {
+        self.requests_per_second = Some(rate);
+        self
+    }
+
+    /// Sets the value of `max_attempts`.
+    pub fn max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = Some(attempts);
+        self
+    }
+
+    /// Sets the value of `retry_delay`.
+    pub fn retry_delay(mut self, delay: f64) -> Self {
+        self.retry_delay = Some(delay);
+        self
+    }
+
+    /// Sets the value of `retry_jitter`.
+    pub fn retry_jitter(mut self, jitter: bool) -> Self {
+        self.retry_jitter = Some(jitter);
+        self
+    }
+
+    /// Sets the value of `request_timeout`.
+    pub fn request_timeout(mut self, seconds: u64) -> Self {
+        self.request_timeout = Some(seconds);
+        self
+    }
+
+    /// Sets the value of `connect_timeout`.
+    pub fn connect_timeout(mut self, seconds: u64) -> Self {
+        self.connect_timeout = Some(seconds);
+        self
+    }
+
+    /// Sets the value of `user_agent`.
+    pub fn user_agent<T>(mut self, user_agent: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.user_agent = Some(user_agent.into());
+        self
+    }
+
+    /// Sets the value of `proxy`.
+    pub fn proxy<T>(mut self, proxy: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.proxy = Some(proxy.into());
+        self
+    }
+
+    /// Sets the value of `headers`.
+    pub fn headers<I, K, V>(mut self, headers: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.headers = Some(
+            headers
+                .into_iter()
+                .map(|(name, value)| (name.into(), value.into()))
+                .collect(),
+        );
+        self
+    }
+
+    /// Sets the value of `base_url`.
+    pub fn base_url(mut self, url: Url) -> Self {
+        self.base_url = Some(url);
+        self
+    }
+
+    /// Sets the value of `cache`.
+    pub fn cache(mut self, cache: bool) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Sets the value of `cache_dir`.
+    pub fn cache_dir<T>(mut self, directory: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.cache_dir = Some(directory.into());
+        self
+    }
+
+    /// Sets the value of `offline`.
+    pub fn offline(mut self, offline: bool) -> Self {
+        self.offline = Some(offline);
+        self
+    }
+
     /// Brings the non-default values of `other` into `self`, overwriting it.
     pub fn merge(mut self, other: ConfigBuilder) -> Self {
         macro_rules! set {
@@ -152,9 +334,26 @@ impl ConfigBuilder {
         set!(tracker_file);
         set!(download_format);
         set!(download_delay);
+        set!(filename_template);
         set!(sensibility_level);
         set!(exec);
         set!(quiet);
+        set!(archive);
+        set!(archive_retention);
+        set!(max_concurrent_requests);
+        set!(requests_per_second);
+        set!(max_attempts);
+        set!(retry_delay);
+        set!(retry_jitter);
+        set!(request_timeout);
+        set!(connect_timeout);
+        set!(user_agent);
+        set!(proxy);
+        set!(headers);
+        set!(base_url);
+        set!(cache);
+        set!(cache_dir);
+        set!(offline);
 
         self
     }
@@ -246,14 +445,26 @@ pub struct Config {
     ///
     /// Defaults to [`default_user_tracker_file()`].
     pub tracker_file: PathBuf,
-    /// The format in which to download the stories.
+    /// The formats in which to download the stories, without duplicates and never empty.
+    ///
+    /// The first one is considered the main format, see [`Config::download_format()`].
     ///
-    /// Defaults to [`DownloadFormat::HTML`].
-    pub download_format: DownloadFormat,
+    /// Defaults to `[DownloadFormat::HTML]`.
+    pub download_formats: Vec<DownloadFormat>,
     /// The seconds to wait between each download.
     ///
     /// Defaults to `5`.
     pub download_delay: u64,
+    /// Template of the path, relative to `download_dir`, where stories are downloaded to when
+    /// downloading directly from Fimfiction.
+    ///
+    /// It's expanded with [`env_with_command_context()`](crate::utils::env_with_command_context)
+    /// and can contain subdirectories separated by `/`, see
+    /// [`download_path()`](crate::utils::download_path) for details. If [`String`] is empty, it
+    /// ends up being the default.
+    ///
+    /// Defaults to `"$TITLE.$FORMAT"`.
+    pub filename_template: String,
     /// The parameters to consider for the conclusion that a story has a relevant update or not.
     ///
     /// Defaults to [`SensibilityLevel::OnlyChapters`].
@@ -269,12 +480,175 @@ pub struct Config {
     ///
     /// Defaults to `false`.
     pub quiet: bool,
+    /// Whether or not to keep the previous copy of a story when downloading it again from
+    /// Fimfiction, see the [`archive`](crate::archive) module.
+    ///
+    /// Defaults to `false`.
+    pub archive: bool,
+    /// The maximum amount of previous copies to keep of each story and file extension, where `0`
+    /// means no limit.
+    ///
+    /// Defaults to `0`.
+    pub archive_retention: usize,
+    /// The maximum amount of story data requests that can be in-flight at the same time when
+    /// checking for updates, where `0` is treated as `1`.
+    ///
+    /// Defaults to `1`.
+    pub max_concurrent_requests: usize,
+    /// The maximum amount of requests to make to Fimfiction per second, where `0` means no limit.
+    ///
+    /// Defaults to `0`.
+    pub requests_per_second: f64,
+    /// The maximum amount of attempts, including the first one, for a request that fails with a
+    /// transient error, where `0` is treated as `1`.
+    ///
+    /// Defaults to `3`.
+    pub max_attempts: u32,
+    /// The seconds to wait before the first retry of a failed request, doubled on each following
+    /// attempt.
+    ///
+    /// Defaults to `1`.
+    pub retry_delay: f64,
+    /// Whether or not to randomly shorten the wait between attempts.
+    ///
+    /// Defaults to `true`.
+    pub retry_jitter: bool,
+    /// The maximum amount of seconds that a request can take, including the download of its
+    /// body, where `0` means no limit.
+    ///
+    /// Defaults to `600`.
+    pub request_timeout: u64,
+    /// The maximum amount of seconds to wait for a connection to be established, where `0` means
+    /// no limit.
+    ///
+    /// Defaults to `30`.
+    pub connect_timeout: u64,
+    /// The value of the `User-Agent` header sent with every request. If [`String`] is empty, it
+    /// ends up being the default.
+    ///
+    /// Defaults to [`DEFAULT_USER_AGENT`].
+    pub user_agent: String,
+    /// URL of the HTTP, HTTPS or SOCKS5 proxy to send every request through.
+    ///
+    /// If [`String`] is empty, it ends up being converted into a `None`.
+    ///
+    /// Defaults to `None`.
+    pub proxy: Option<String>,
+    /// Extra headers to send with every request, by name.
+    ///
+    /// Defaults to no headers.
+    pub headers: BTreeMap<String, String>,
+    /// The URL that every request is made to instead of Fimfiction, as a mirror or a caching
+    /// proxy, see [`Config::site_url()`].
+    ///
+    /// Defaults to [`DEFAULT_BASE_URL`].
+    pub base_url: Url,
+    /// Whether or not to keep the responses from Fimfiction to make the next requests for them
+    /// conditional, see the [`cache`](crate::cache) module.
+    ///
+    /// Defaults to `false`.
+    pub cache: bool,
+    /// Path to the directory where the responses are kept, expanding tilde into home directory.
+    ///
+    /// Defaults to [`default_user_cache_dir()`].
+    pub cache_dir: PathBuf,
+    /// Whether or not to answer every request with the response kept in the
+    /// [`cache`](crate::cache) for it instead of making it, failing with
+    /// [`ErrorKind::Offline`](crate::errors::ErrorKind::Offline) when there is none.
+    ///
+    /// Defaults to `false`.
+    pub offline: bool,
+}
+
+impl Config {
+    /// Sets `download_formats` to `formats` without duplicates, if not empty.
+    fn set_download_formats(&mut self, formats: Vec<DownloadFormat>) {
+        let mut unique_formats = Vec::with_capacity(formats.len());
+        for format in formats {
+            if !unique_formats.contains(&format) {
+                unique_formats.push(format);
+            }
+        }
+
+        if !unique_formats.is_empty() {
+            self.download_formats = unique_formats;
+        }
+    }
+
+    /// Constructs the configuration that applies to `story`, with the values in its
+    /// [`overrides`](crate::story::StoryOverrides) taking precedence over the ones of `self`.
+    pub fn for_story(&self, story: &Story) -> Config {
+        let overrides = &story.overrides;
+        let mut config = self.clone();
+
+        if let Some(formats) = overrides.download_format.as_ref() {
+            config.set_download_formats(formats.clone());
+        }
+
+        if let Some(exec) = overrides.exec.as_ref() {
+            config.exec = Some(exec.clone()).filter(|exec| !exec.is_empty());
+        }
+
+        if let Some(level) = overrides.sensibility_level {
+            config.sensibility_level = level;
+        }
+
+        if let Some(path) = overrides.download_dir.as_ref() {
+            if !path.is_empty() {
+                config.download_dir = shellexpand::tilde(path).into_owned().into();
+            }
+        }
+
+        config
+    }
+
+    /// Joins `path` to `base_url`, as if the latter were a directory even without a trailing
+    /// slash.
+    /// ```
+    /// # use fimfic_tracker::{Config, ConfigBuilder};
+    /// let config = Config::default();
+    /// assert_eq!(
+    ///     config.site_url("story/165").as_str(),
+    ///     "https://www.fimfiction.net/story/165"
+    /// );
+    ///
+    /// let config: Config = ConfigBuilder::new()
+    ///     .base_url("http://localhost:8080/fimfiction".parse().unwrap())
+    ///     .into();
+    /// assert_eq!(
+    ///     config.site_url("story/165").as_str(),
+    ///     "http://localhost:8080/fimfiction/story/165"
+    /// );
+    /// ```
+    pub fn site_url(&self, path: &str) -> Url {
+        let mut base_url = self.base_url.clone();
+        if !base_url.path().ends_with('/') {
+            let directory = format!("{}/", base_url.path());
+            base_url.set_path(&directory);
+        }
+
+        base_url
+            .join(path)
+            .expect("path should be joinable to the base URL")
+    }
+
+    /// The main download format, the first one in `download_formats`.
+    ///
+    /// It's the one used by the `exec` command for the `FORMAT` and `DOWNLOAD_URL` variables.
+    pub fn download_format(&self) -> DownloadFormat {
+        self.download_formats
+            .first()
+            .copied()
+            .unwrap_or(DownloadFormat::HTML)
+    }
 }
 
 lazy_static! {
     static ref DEFAULT_DOWNLOAD_DIR: PathBuf = UserDirs::new()
         .and_then(|dirs| dirs.download_dir().map(|path| path.to_path_buf()))
         .expect("user download dir should be retrievable");
+    static ref DEFAULT_BASE_URL_PARSED: Url =
+        Url::parse(DEFAULT_BASE_URL).expect("default base URL should be valid");
 }
 
 impl Default for Config {
@@ -282,11 +656,28 @@ impl Default for Config {
         Self {
             download_dir: DEFAULT_DOWNLOAD_DIR.clone(),
             tracker_file: default_user_tracker_file(),
-            download_format: DownloadFormat::HTML,
+            download_formats: vec![DownloadFormat::HTML],
             download_delay: 5,
+            filename_template: DEFAULT_FILENAME_TEMPLATE.into(),
             sensibility_level: SensibilityLevel::OnlyChapters,
             exec: None,
             quiet: false,
+            archive: false,
+            archive_retention: 0,
+            max_concurrent_requests: 1,
+            requests_per_second: 0.0,
+            max_attempts: 3,
+            retry_delay: 1.0,
+            retry_jitter: true,
+            request_timeout: 600,
+            connect_timeout: 30,
+            user_agent: DEFAULT_USER_AGENT.into(),
+            proxy: None,
+            headers: BTreeMap::new(),
+            base_url: DEFAULT_BASE_URL_PARSED.clone(),
+            cache: false,
+            cache_dir: default_user_cache_dir(),
+            offline: false,
         }
     }
 }
@@ -307,14 +698,20 @@ impl From<ConfigBuilder> for Config {
             }
         }
 
-        if let Some(format) = builder.download_format {
-            config.download_format = format;
+        if let Some(formats) = builder.download_format {
+            config.set_download_formats(formats);
         }
 
         if let Some(delay) = builder.download_delay {
             config.download_delay = delay;
         }
 
+        if let Some(template) = builder.filename_template {
+            if !template.is_empty() {
+                config.filename_template = template;
+            }
+        }
+
         if let Some(level) = builder.sensibility_level {
             config.sensibility_level = level;
         }
@@ -329,6 +726,76 @@ impl From<ConfigBuilder> for Config {
             config.quiet = quiet;
         }
 
+        if let Some(archive) = builder.archive {
+            config.archive = archive;
+        }
+
+        if let Some(retention) = builder.archive_retention {
+            config.archive_retention = retention;
+        }
+
+        if let Some(max) = builder.max_concurrent_requests {
+            config.max_concurrent_requests = max;
+        }
+
+        if let Some(rate) = builder.requests_per_second {
+            config.requests_per_second = rate;
+        }
+
+        if let Some(attempts) = builder.max_attempts {
+            config.max_attempts = attempts;
+        }
+
+        if let Some(delay) = builder.retry_delay {
+            config.retry_delay = delay;
+        }
+
+        if let Some(jitter) = builder.retry_jitter {
+            config.retry_jitter = jitter;
+        }
+
+        if let Some(seconds) = builder.request_timeout {
+            config.request_timeout = seconds;
+        }
+
+        if let Some(seconds) = builder.connect_timeout {
+            config.connect_timeout = seconds;
+        }
+
+        if let Some(user_agent) = builder.user_agent {
+            if !user_agent.is_empty() {
+                config.user_agent = user_agent;
+            }
+        }
+
+        if let Some(proxy) = builder.proxy {
+            if !proxy.is_empty() {
+                let _ = config.proxy.insert(proxy);
+            }
+        }
+
+        if let Some(headers) = builder.headers {
+            config.headers = headers;
+        }
+
+        if let Some(url) = builder.base_url {
+            config.base_url = url;
+        }
+
+        if let Some(cache) = builder.cache {
+            config.cache = cache;
+        }
+
+        if let Some(path) = builder.cache_dir {
+            if !path.is_empty() {
+                config.cache_dir = shellexpand::tilde(&path).into_owned().into();
+            }
+        }
+
+        if let Some(offline) = builder.offline {
+            config.offline = offline;
+        }
+
         config
     }
 }
@@ -402,37 +869,113 @@ mod test {
             [from_file: config_path!("test-config.toml")]
             download_dir = "~/some/path/to/dir";
             tracker_file = "~/path/of/file.json";
-            download_format = DownloadFormat::EPUB;
+            download_formats = [DownloadFormat::EPUB, DownloadFormat::TXT];
             download_delay = 10;
+            filename_template = "$AUTHOR/$TITLE ($ID).$FORMAT";
             sensibility_level = SensibilityLevel::IncludeWords;
             exec = "wget -O ${download_dir}/${safe_title} https://www.fimfiction.net/story/download/${id}/${html}";
             quiet = false;
+            archive = true;
+            archive_retention = 3;
+            max_concurrent_requests = 8;
+            requests_per_second = 2.5;
+            max_attempts = 5;
+            retry_delay = 0.5;
+            retry_jitter = false;
+            request_timeout = 120;
+            connect_timeout = 10;
+            user_agent = "my-tracker/1.0";
+            proxy = "socks5://127.0.0.1:9050";
+            headers = [("Accept-Language", "en"), ("X-Requested-By", "fimfic-tracker")];
+            base_url = Url::parse("http://localhost:8080/fimfiction").unwrap();
+            cache = true;
+            cache_dir = "~/path/to/cache";
+            offline = true;
         );
 
         set_config_vars!(
             "DOWNLOAD_DIR" => "~/some/path/to/dir",
             "TRACKER_FILE" => "~/path/of/file.json",
-            "DOWNLOAD_FORMAT" => "txt",
+            "DOWNLOAD_FORMAT" => "txt, html,txt",
             "DOWNLOAD_DELAY" => "0",
+            "FILENAME_TEMPLATE" => "$UPDATE_DATE - $TITLE.$FORMAT",
             "SENSIBILITY_LEVEL" => "2",
             "EXEC" => "/path/to/some/script --dir ${download_dir} $id",
-            "QUIET" => "false"
+            "QUIET" => "false",
+            "ARCHIVE" => "true",
+            "ARCHIVE_RETENTION" => "10",
+            "MAX_CONCURRENT_REQUESTS" => "4",
+            "REQUESTS_PER_SECOND" => "10",
+            "MAX_ATTEMPTS" => "1",
+            "RETRY_DELAY" => "2",
+            "RETRY_JITTER" => "true",
+            "REQUEST_TIMEOUT" => "0",
+            "CONNECT_TIMEOUT" => "5",
+            "USER_AGENT" => "",
+            "PROXY" => "http://proxy.example.com:8080",
+            "BASE_URL" => "https://mirror.example.com/",
+            "CACHE" => "false",
+            "CACHE_DIR" => "/tmp/fimfic-tracker-cache",
+            "OFFLINE" => "false"
         );
 
         assert_config_source!(
             [from_env: ENV_PREFIX_TEST]
             download_dir = "~/some/path/to/dir";
             tracker_file = "~/path/of/file.json";
-            download_format = DownloadFormat::TXT;
+            download_formats = [DownloadFormat::TXT, DownloadFormat::HTML];
             download_delay = 0;
+            filename_template = "$UPDATE_DATE - $TITLE.$FORMAT";
             sensibility_level = SensibilityLevel::Anything;
             exec = "/path/to/some/script --dir ${download_dir} $id";
             quiet = false;
+            archive = true;
+            archive_retention = 10;
+            max_concurrent_requests = 4;
+            requests_per_second = 10.0;
+            max_attempts = 1;
+            retry_delay = 2.0;
+            retry_jitter = true;
+            request_timeout = 0;
+            connect_timeout = 5;
+            proxy = "http://proxy.example.com:8080";
+            base_url = Url::parse("https://mirror.example.com/").unwrap();
+            cache = false;
+            cache_dir = "/tmp/fimfic-tracker-cache";
+            offline = false;
         );
 
         Ok(())
     }
 
+    #[test]
+    fn story_overrides() {
+        use crate::story::StoryOverrides;
+
+        let config: Config = ConfigBuilder::new()
+            .download_dir("/path/to/download")
+            .exec("/path/to/script $ID")
+            .into();
+        let mut story = Story::default_for_test(1);
+
+        assert_eq!(config.for_story(&story), config);
+
+        story.overrides = StoryOverrides {
+            download_format: Some(vec![DownloadFormat::EPUB, DownloadFormat::EPUB]),
+            exec: Some("".into()),
+            sensibility_level: Some(SensibilityLevel::Anything),
+            download_dir: Some("/path/to/other".into()),
+            skip_download: Some(true),
+        };
+
+        let expect: Config = ConfigBuilder::new()
+            .download_dir("/path/to/other")
+            .download_format(DownloadFormat::EPUB)
+            .sensibility_level(SensibilityLevel::Anything)
+            .into();
+        assert_eq!(config.for_story(&story), expect);
+    }
+
     #[test]
     fn merging_result() {
         let config = ConfigBuilder::new()
@@ -1,7 +1,7 @@
-use std::{cmp, fmt};
+use std::{cmp, fmt, str::FromStr};
 
 use serde::de::{self, Unexpected, Visitor};
-use serde::{Deserialize, Deserializer};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 /// The different available options of update sensibility level.
 ///
@@ -61,6 +61,22 @@ impl Ord for SensibilityLevel {
     }
 }
 
+impl FromStr for SensibilityLevel {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let level = value.parse::<i64>().map_err(|_| {
+            format!(
+                "invalid value `{}`, expected an integer between 0 and 2",
+                value
+            )
+        })?;
+        SensibilityVisitor
+            .visit_i64(level)
+            .map_err(|err: de::value::Error| err.to_string())
+    }
+}
+
 struct SensibilityVisitor;
 
 impl<'de> Visitor<'de> for SensibilityVisitor {
@@ -81,6 +97,25 @@ impl<'de> Visitor<'de> for SensibilityVisitor {
             _ => Err(E::invalid_value(Unexpected::Signed(value), &self)),
         }
     }
+
+    fn visit_u64<E>(self, value: u64) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        match i64::try_from(value) {
+            Ok(value) => self.visit_i64(value),
+            Err(_) => Err(E::invalid_value(Unexpected::Unsigned(value), &self)),
+        }
+    }
+}
+
+impl Serialize for SensibilityLevel {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_u8(*self as u8)
+    }
 }
 
 impl<'de> Deserialize<'de> for SensibilityLevel {
@@ -107,4 +142,13 @@ mod test {
 
         assert!(SensibilityLevel::Anything == SensibilityLevel::Anything);
     }
+
+    #[test]
+    fn from_str() {
+        assert_eq!("0".parse(), Ok(SensibilityLevel::OnlyChapters));
+        assert_eq!("1".parse(), Ok(SensibilityLevel::IncludeWords));
+        assert_eq!("2".parse(), Ok(SensibilityLevel::Anything));
+        assert!("3".parse::<SensibilityLevel>().is_err());
+        assert!("all".parse::<SensibilityLevel>().is_err());
+    }
 }
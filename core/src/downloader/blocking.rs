@@ -1,3 +1,5 @@
+use std::process::ExitStatus;
+
 use tokio::runtime::Runtime;
 
 use crate::config::Config;
@@ -6,7 +8,7 @@ use crate::story::{Id, Story};
 use crate::StoryResponse;
 
 use super::listener::ProgressListener;
-use super::sync::AsyncRequester;
+use super::sync::{AsyncRequester, Downloaded, DownloadedFile};
 
 /// A blocking story downloader.
 ///
@@ -20,7 +22,7 @@ use super::sync::AsyncRequester;
 /// use fimfic_tracker::downloader::{BlockingRequester, SilentListener};
 /// # let config = Config::default();
 ///
-/// let requester = BlockingRequester::new(config, SilentListener);
+/// let requester = BlockingRequester::new(config, SilentListener)?;
 ///
 /// // Requesting "The Moon's Apprentice" by Forthwith
 /// let story = requester.get_story_response(196256)?;
@@ -43,28 +45,54 @@ impl<P> BlockingRequester<P>
 where
     P: ProgressListener,
 {
-    /// Constructs a new [`BlockingRequester`].
-    pub fn new(config: Config, progress: P) -> Self {
+    /// Constructs a new [`BlockingRequester`], as in [`AsyncRequester::new()`].
+    ///
+    /// # Errors
+    ///
+    /// According to [`AsyncRequester::new()`].
+    pub fn new(config: Config, progress: P) -> errors::Result<Self> {
+        Ok(BlockingRequester {
+            inner: AsyncRequester::new(config, progress)?,
+            rt: Runtime::new().unwrap(),
+        })
+    }
+
+    /// Constructs a new [`BlockingRequester`], as in [`AsyncRequester::with_client()`].
+    pub fn with_client(config: Config, client: reqwest::Client, progress: P) -> Self {
         BlockingRequester {
-            inner: AsyncRequester::new(config, progress),
+            inner: AsyncRequester::with_client(config, client, progress),
             rt: Runtime::new().unwrap(),
         }
     }
 
+    /// The [`ProgressListener`] that is told about the progress of the requester.
+    pub(crate) fn listener(&self) -> &P {
+        self.inner.listener()
+    }
+
     /// Executes [`AsyncRequester::get_story_response()`] on a synchronous context.
     pub fn get_story_response(&self, id: Id) -> errors::Result<StoryResponse> {
         self.rt
             .block_on(async { self.inner.get_story_response(id).await })
     }
 
+    /// Executes [`AsyncRequester::get_story_responses()`] on a synchronous context.
+    pub fn get_story_responses<I>(&self, ids: I) -> Vec<(Id, errors::Result<StoryResponse>)>
+    where
+        I: IntoIterator<Item = Id>,
+    {
+        self.rt
+            .block_on(async { self.inner.get_story_responses(ids).await })
+    }
+
     /// Executes [`AsyncRequester::client_download()`] on a synchronous context.
-    pub fn client_download(&self, story: &Story) -> errors::Result<()> {
+    pub fn client_download(&self, story: &Story) -> errors::Result<Vec<DownloadedFile>> {
         self.rt
             .block_on(async { self.inner.client_download(story).await })
     }
 
     /// Executes [`AsyncRequester::exec_download()`] on a synchronous context.
-    pub fn exec_download<S>(&self, command: S, story: &Story) -> errors::Result<()>
+    pub fn exec_download<S>(&self, command: S, story: &Story) -> errors::Result<ExitStatus>
     where
         S: AsRef<str>,
     {
@@ -73,7 +101,7 @@ where
     }
 
     /// Executes [`AsyncRequester::download()`] on a synchronous context.
-    pub fn download(&self, story: &Story) -> errors::Result<()> {
+    pub fn download(&self, story: &Story) -> errors::Result<Downloaded> {
         self.rt.block_on(async { self.inner.download(story).await })
     }
 }
@@ -0,0 +1,91 @@
+use tokio::sync::Mutex;
+use tokio::time::{self, Duration, Instant};
+
+/// The longest wait between requests, for rates so low that it would be longer.
+const MAX_INTERVAL: Duration = Duration::from_secs(60 * 60 * 24);
+
+/// Limits the rate at which requests are made, shared between all of the requests of a requester.
+pub(crate) struct RateLimiter {
+    interval: Option<Duration>,
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    /// Constructs a new [`RateLimiter`] that allows up to `requests_per_second` requests each
+    /// second, where `0` (or anything that isn't a positive number) means no limit.
+    ///
+    /// The wait between requests is at most [`MAX_INTERVAL`], no matter how low the rate is.
+    pub fn new(requests_per_second: f64) -> Self {
+        let interval = if requests_per_second > 0.0 {
+            Some(
+                Duration::try_from_secs_f64(1.0 / requests_per_second)
+                    .map_or(MAX_INTERVAL, |interval| interval.min(MAX_INTERVAL)),
+            )
+        } else {
+            None
+        };
+
+        RateLimiter {
+            interval,
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Waits until the next request is allowed to be made.
+    pub async fn wait(&self) {
+        let interval = match self.interval {
+            Some(interval) => interval,
+            None => return,
+        };
+
+        // The lock is held while sleeping, so the waiting requests go through one at a time.
+        let mut next = self.next.lock().await;
+        time::sleep_until(*next).await;
+        *next = Instant::now() + interval;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn waits_between_requests() {
+        let limiter = RateLimiter::new(20.0);
+        let start = Instant::now();
+
+        for _ in 0..5 {
+            limiter.wait().await;
+        }
+
+        // The first request goes through right away.
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+
+    #[tokio::test]
+    async fn no_limit() {
+        let limiter = RateLimiter::new(0.0);
+        let start = Instant::now();
+
+        for _ in 0..100 {
+            limiter.wait().await;
+        }
+
+        assert!(start.elapsed() < Duration::from_millis(100));
+    }
+
+    #[test]
+    fn out_of_range_rates() {
+        assert_eq!(RateLimiter::new(1e-300).interval, Some(MAX_INTERVAL));
+        assert_eq!(
+            RateLimiter::new(f64::MIN_POSITIVE).interval,
+            Some(MAX_INTERVAL)
+        );
+        assert_eq!(
+            RateLimiter::new(f64::INFINITY).interval,
+            Some(Duration::ZERO)
+        );
+        assert_eq!(RateLimiter::new(f64::NAN).interval, None);
+        assert_eq!(RateLimiter::new(-1.0).interval, None);
+    }
+}
@@ -1,13 +1,70 @@
-use crate::story::Story;
+use std::path::Path;
+use std::process::ExitStatus;
+use std::time::Duration;
+
+use crate::config::DownloadFormat;
+use crate::errors::TrackerError;
+use crate::story::{Id, Story, StoryUpdate};
+
+/// State of a story download, as given to [`ProgressListener::download_progress()`].
+#[derive(Debug, Clone, Copy)]
+pub struct DownloadProgress<'a> {
+    /// The story being downloaded.
+    pub story: &'a Story,
+    /// Path to the file being written into.
+    pub filepath: &'a str,
+    /// The total amount of bytes downloaded. It **always** starts at `0`.
+    pub bytes: usize,
+    /// The expected amount of bytes, from the `Content-Length` of the response if present.
+    pub total: Option<usize>,
+    /// Time since the download started.
+    pub elapsed: Duration,
+}
+
+impl DownloadProgress<'_> {
+    /// Whether some bytes were already downloaded.
+    pub fn is_started(&self) -> bool {
+        self.bytes != 0
+    }
+
+    /// Percentage of the `total` that was downloaded, from `0.0` to `100.0`.
+    pub fn percentage(&self) -> Option<f64> {
+        self.total.map(|total| match total {
+            0 => 100.0,
+            total => (self.bytes as f64 / total as f64 * 100.0).min(100.0),
+        })
+    }
+
+    /// Average amount of bytes downloaded per second, once some time has passed.
+    pub fn speed(&self) -> Option<f64> {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds > 0.0 {
+            Some(self.bytes as f64 / seconds)
+        } else {
+            None
+        }
+    }
+
+    /// Estimated time left until the download finishes, according to its average [`speed()`].
+    ///
+    /// [`speed()`]: DownloadProgress::speed
+    pub fn eta(&self) -> Option<Duration> {
+        let remaining = self.total?.saturating_sub(self.bytes);
+        match self.speed() {
+            Some(speed) if speed > 0.0 => Some(Duration::from_secs_f64(remaining as f64 / speed)),
+            _ => None,
+        }
+    }
+}
 
 /// Listener for requesters in the download progress.
 pub trait ProgressListener {
-    /// Executed for each chunk of bytes that is written into `filepath`, where `bytes` is the
-    /// total amount of bytes downloaded. It **always** start at `0`.
+    /// Executed for each chunk of bytes that is written into a file, including once before the
+    /// first one.
     ///
     /// On `client_download` method.
-    fn download_progress(&self, bytes: usize, filepath: &str);
-    /// Executed once the download of a story has finished.
+    fn download_progress(&self, progress: &DownloadProgress);
+    /// Executed once the download of a story has finished in every format.
     ///
     /// On `client_download` method.
     fn successfull_client_download(&self, story: &Story);
@@ -19,14 +76,94 @@ pub trait ProgressListener {
     ///
     /// On `exec_download` method.
     fn successfull_command_execution(&self, story: &Story);
+
+    /// Executed once the download of a story in `format` into `filepath` has finished, before
+    /// starting with the next format.
+    ///
+    /// On `client_download` method.
+    fn successfull_format_download(
+        &self,
+        _story: &Story,
+        _format: DownloadFormat,
+        _filepath: &Path,
+    ) {
+    }
+    /// Executed instead of `successfull_format_download` when the story downloaded in `format`
+    /// has the same content as the last time, leaving the file in `filepath` untouched.
+    ///
+    /// Defaults to calling `successfull_format_download`.
+    ///
+    /// On `client_download` method.
+    fn unchanged_format_download(&self, story: &Story, format: DownloadFormat, filepath: &Path) {
+        self.successfull_format_download(story, format, filepath);
+    }
+    /// Executed just before requesting the current data of a story to check it for updates.
+    ///
+    /// On the check functions of [`checker`](crate::checker).
+    fn before_story_check(&self, _story: &Story) {}
+    /// Executed once a story was compared with its current data, with the `update` found if any.
+    ///
+    /// On the check functions of [`checker`](crate::checker).
+    fn story_checked(&self, _story: &Story, _update: Option<&StoryUpdate>) {}
+    /// Executed when a story that has an update is set to skip its downloads, right after
+    /// `story_checked`.
+    ///
+    /// On the check functions of [`checker`](crate::checker).
+    fn story_skipped(&self, _story: &Story) {}
+    /// Executed when the download of a story from Fimfiction fails, with the `error` returned.
+    ///
+    /// On `client_download` method.
+    fn download_failed(&self, _story: &Story, _error: &TrackerError) {}
+    /// Executed when the command exits with an unsuccessful `status`.
+    ///
+    /// On `exec_download` method.
+    fn command_failed(&self, _story: &Story, _status: ExitStatus) {}
+    /// Executed when a request related to the story of ID `id` fails with a transient `error`,
+    /// before waiting `delay` to make the `attempt` that follows, counting from `1`.
+    ///
+    /// On every method that makes requests.
+    fn retrying(&self, _id: Id, _attempt: u32, _delay: Duration, _error: &TrackerError) {}
 }
 
 /// A [`ProgressListener`] implementation that does nothing.
 pub struct SilentListener;
 
 impl ProgressListener for SilentListener {
-    fn download_progress(&self, _bytes: usize, _filepath: &str) {}
+    fn download_progress(&self, _progress: &DownloadProgress) {}
     fn successfull_client_download(&self, _story: &Story) {}
     fn before_execute_command(&self, _story: &Story) {}
     fn successfull_command_execution(&self, _story: &Story) {}
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn progress_estimations() {
+        let story = Story::default_for_test(165);
+        let mut progress = DownloadProgress {
+            story: &story,
+            filepath: "A Title.html",
+            bytes: 0,
+            total: Some(4000),
+            elapsed: Duration::ZERO,
+        };
+
+        assert!(!progress.is_started());
+        assert_eq!(progress.percentage(), Some(0.0));
+        assert_eq!(progress.speed(), None);
+        assert_eq!(progress.eta(), None);
+
+        progress.bytes = 1000;
+        progress.elapsed = Duration::from_secs(2);
+        assert!(progress.is_started());
+        assert_eq!(progress.percentage(), Some(25.0));
+        assert_eq!(progress.speed(), Some(500.0));
+        assert_eq!(progress.eta(), Some(Duration::from_secs(6)));
+
+        progress.total = None;
+        assert_eq!(progress.percentage(), None);
+        assert_eq!(progress.eta(), None);
+    }
+}
@@ -1,9 +1,11 @@
 //! Ready to use data and story downloader.
 
 mod blocking;
+mod limiter;
 mod listener;
+mod retry;
 mod sync;
 
 pub use blocking::BlockingRequester;
-pub use listener::{ProgressListener, SilentListener};
-pub use sync::AsyncRequester;
+pub use listener::{DownloadProgress, ProgressListener, SilentListener};
+pub use sync::{build_client, AsyncRequester, Downloaded, DownloadedFile};
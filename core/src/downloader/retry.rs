@@ -0,0 +1,309 @@
+use std::future::Future;
+
+use chrono::{DateTime, Utc};
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::StatusCode;
+use tokio::time::{self, Duration};
+
+use crate::config::Config;
+use crate::errors::{self, ErrorKind, StoryError, TrackerError};
+use crate::story::Id;
+
+use super::listener::ProgressListener;
+
+/// The maximum delay between two attempts computed from the base delay.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// The maximum delay asked for by a `Retry-After` header that is honored, giving up instead if
+/// it's longer.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(300);
+
+/// An error from an attempt, with the delay asked for by the server before trying again, if any.
+#[derive(Debug)]
+pub(crate) struct Failure {
+    pub error: TrackerError,
+    pub retry_after: Option<Duration>,
+}
+
+impl Failure {
+    /// Gives additional context to the error message.
+    pub fn context<C>(mut self, context: C) -> Self
+    where
+        C: Into<String>,
+    {
+        self.error = self.error.context(context);
+        self
+    }
+}
+
+impl From<TrackerError> for Failure {
+    fn from(error: TrackerError) -> Self {
+        Failure {
+            error,
+            retry_after: None,
+        }
+    }
+}
+
+/// Whether `status` signals a failure that is likely to go away on its own.
+pub(crate) fn is_transient_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// Whether `error` is likely to go away on its own, be it a connection error, a timeout, an
+/// interrupted response body, a [transient status](is_transient_status) or a truncated API
+/// response.
+pub(crate) fn is_transient(error: &TrackerError) -> bool {
+    match &error.kind {
+        ErrorKind::Request(err) => {
+            err.is_connect()
+                || err.is_timeout()
+                || err.is_body()
+                || err.status().map(is_transient_status).unwrap_or(false)
+        }
+        ErrorKind::UnexpectedResponse {
+            error: StoryError::Json(err),
+            ..
+        } => err.is_syntax() || err.is_eof(),
+        _ => false,
+    }
+}
+
+/// Reads the delay asked for by the `Retry-After` header, be it in seconds or as an HTTP date.
+pub(crate) fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        date.with_timezone(&Utc)
+            .signed_duration_since(Utc::now())
+            .to_std()
+            .unwrap_or(Duration::ZERO),
+    )
+}
+
+/// Policy on how many times and how often to retry an operation that failed with a
+/// [transient error](is_transient).
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct RetryPolicy {
+    max_attempts: u32,
+    base_delay: Duration,
+    jitter: bool,
+}
+
+impl RetryPolicy {
+    /// Constructs a new [`RetryPolicy`] from the retry options of `config`.
+    pub fn new(config: &Config) -> Self {
+        RetryPolicy {
+            max_attempts: config.max_attempts.max(1),
+            base_delay: Duration::try_from_secs_f64(config.retry_delay.max(0.0))
+                .map_or(MAX_RETRY_DELAY, |delay| delay.min(MAX_RETRY_DELAY)),
+            jitter: config.retry_jitter,
+        }
+    }
+
+    /// The delay to wait before the attempt that follows `attempt`, doubling the base delay with
+    /// each attempt.
+    ///
+    /// With jitter enabled, it's randomly shortened up to a half so that concurrent requests
+    /// don't retry at the same time.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        let delay = self
+            .base_delay
+            .checked_mul(factor)
+            .unwrap_or(MAX_RETRY_DELAY)
+            .min(MAX_RETRY_DELAY);
+
+        if self.jitter {
+            delay.mul_f64(0.5 + fastrand::f64() / 2.0)
+        } else {
+            delay
+        }
+    }
+
+    /// Executes `operation` until it succeeds, it fails with an error that isn't
+    /// [transient](is_transient) or the maximum amount of attempts is reached.
+    ///
+    /// Each retry is told to `listener` as related to the story of ID `id`.
+    ///
+    /// # Errors
+    ///
+    /// The error of the last attempt, with the amount of attempts made added to its context when
+    /// more than one was made.
+    pub async fn run<T, F, Fut, P>(
+        &self,
+        id: Id,
+        listener: &P,
+        mut operation: F,
+    ) -> errors::Result<T>
+    where
+        P: ProgressListener,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, Failure>>,
+    {
+        let mut attempt = 1;
+
+        loop {
+            let failure = match operation().await {
+                Ok(value) => return Ok(value),
+                Err(failure) => failure,
+            };
+
+            let delay = match failure.retry_after {
+                Some(delay) if delay > MAX_RETRY_AFTER => None,
+                Some(delay) => Some(delay),
+                None => Some(self.delay(attempt)),
+            };
+
+            match delay {
+                Some(delay) if attempt < self.max_attempts && is_transient(&failure.error) => {
+                    debug!(
+                        "Attempt {} failed, retrying in {:?}: {}",
+                        attempt, delay, failure.error
+                    );
+                    attempt += 1;
+                    listener.retrying(id, attempt, delay, &failure.error);
+                    time::sleep(delay).await;
+                }
+                _ if attempt > 1 => {
+                    return Err(failure
+                        .error
+                        .append_context(format!("gave up after {} attempts", attempt)));
+                }
+                _ => return Err(failure.error),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::cell::Cell;
+
+    use reqwest::header::HeaderValue;
+
+    use crate::config::ConfigBuilder;
+    use crate::downloader::SilentListener;
+
+    fn policy(max_attempts: u32, retry_delay: f64, retry_jitter: bool) -> RetryPolicy {
+        RetryPolicy::new(
+            &ConfigBuilder::new()
+                .max_attempts(max_attempts)
+                .retry_delay(retry_delay)
+                .retry_jitter(retry_jitter)
+                .into(),
+        )
+    }
+
+    fn syntax_error() -> TrackerError {
+        let error = serde_json::from_str::<serde_json::Value>("{\"story\":")
+            .expect_err("JSON should be truncated");
+        TrackerError::unexpected_response(StoryError::Json(error), 1, "{\"story\":".into())
+    }
+
+    #[test]
+    fn exponential_delay() {
+        let policy = policy(5, 1.0, false);
+        assert_eq!(policy.delay(1), Duration::from_secs(1));
+        assert_eq!(policy.delay(2), Duration::from_secs(2));
+        assert_eq!(policy.delay(3), Duration::from_secs(4));
+        assert_eq!(policy.delay(40), MAX_RETRY_DELAY);
+
+        let policy = self::policy(5, 1.0, true);
+        for attempt in 1..5 {
+            let delay = policy.delay(attempt);
+            let max = Duration::from_secs(1 << (attempt - 1));
+            assert!(delay >= max / 2 && delay <= max);
+        }
+    }
+
+    #[test]
+    fn out_of_range_delays() {
+        assert_eq!(policy(5, f64::INFINITY, false).delay(1), MAX_RETRY_DELAY);
+        assert_eq!(policy(5, 1e300, false).delay(1), MAX_RETRY_DELAY);
+        assert_eq!(policy(5, f64::NAN, false).delay(1), Duration::ZERO);
+        assert_eq!(policy(5, -1.0, false).delay(1), Duration::ZERO);
+    }
+
+    #[test]
+    fn retry_after_header() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers), None);
+
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("120"));
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(120)));
+
+        headers.insert(
+            RETRY_AFTER,
+            HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"),
+        );
+        assert_eq!(retry_after(&headers), Some(Duration::ZERO));
+
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("soon"));
+        assert_eq!(retry_after(&headers), None);
+    }
+
+    #[test]
+    fn transient_errors() {
+        assert!(is_transient(&syntax_error()));
+        assert!(!is_transient(&TrackerError::unexpected_response(
+            StoryError::InvalidId,
+            1,
+            "{\"error\":\"Invalid story id\"}".into()
+        )));
+        assert!(!is_transient(&TrackerError::custom("an error")));
+
+        assert!(is_transient_status(StatusCode::TOO_MANY_REQUESTS));
+        assert!(is_transient_status(StatusCode::BAD_GATEWAY));
+        assert!(!is_transient_status(StatusCode::NOT_FOUND));
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_attempts() {
+        let attempts = Cell::new(0);
+        let err = policy(3, 0.0, false)
+            .run(0, &SilentListener, || async {
+                attempts.set(attempts.get() + 1);
+                Err::<(), _>(Failure::from(syntax_error()))
+            })
+            .await
+            .unwrap_err();
+
+        assert_eq!(attempts.get(), 3);
+        assert!(err.to_string().starts_with("gave up after 3 attempts: "));
+
+        let attempts = Cell::new(0);
+        let err = policy(3, 0.0, false)
+            .run(0, &SilentListener, || async {
+                attempts.set(attempts.get() + 1);
+                Err::<(), _>(Failure::from(TrackerError::custom("an error")))
+            })
+            .await
+            .unwrap_err();
+
+        assert_eq!(attempts.get(), 1);
+        assert_eq!(err.to_string(), "an error");
+
+        let attempts = Cell::new(0);
+        let value = policy(3, 0.0, false)
+            .run(0, &SilentListener, || async {
+                attempts.set(attempts.get() + 1);
+                if attempts.get() < 2 {
+                    Err(Failure::from(syntax_error()))
+                } else {
+                    Ok(attempts.get())
+                }
+            })
+            .await
+            .expect("second attempt should succeed");
+
+        assert_eq!(value, 2);
+    }
+}
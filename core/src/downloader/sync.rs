@@ -1,47 +1,103 @@
-use std::process::Stdio;
-
-use futures_util::StreamExt;
-use reqwest;
-use tokio::{fs, io, process::Command};
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use futures_util::{stream, StreamExt};
+use reqwest::{self, header::HeaderMap, header::HeaderName, header::HeaderValue, StatusCode};
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+use tokio::{fs, io, process::Command, task};
 use url::Url;
 
-use crate::config::Config;
+use crate::archive::replace_file;
+use crate::cache::{self, CachedResponse};
+use crate::config::{Config, DownloadFormat};
 use crate::errors::{self, TrackerError};
 use crate::story::{Id, Story};
-use crate::utils::{download_url_format, env_with_command_context, sanitize_filename};
+use crate::utils::{download_path, download_url_format, env_with_command_context};
 use crate::StoryResponse;
 
-use super::listener::ProgressListener;
+use super::limiter::RateLimiter;
+use super::listener::{DownloadProgress, ProgressListener};
+use super::retry::{self, Failure, RetryPolicy};
 
 async fn download<S, P>(
     res: reqwest::Response,
     mut dest: fs::File,
+    story: &Story,
     filepath: S,
-    progress: &P,
-) -> errors::Result<()>
+    listener: &P,
+) -> errors::Result<String>
 where
     S: ToString,
     P: ProgressListener,
 {
     let filepath = filepath.to_string();
-    let mut total_bytes: usize = 0;
-
-    progress.download_progress(total_bytes, &filepath);
+    let start = Instant::now();
+    let mut progress = DownloadProgress {
+        story,
+        filepath: &filepath,
+        bytes: 0,
+        total: res.content_length().map(|length| length as usize),
+        elapsed: start.elapsed(),
+    };
+    let mut hasher = Sha256::new();
+
+    listener.download_progress(&progress);
 
     let mut stream = res.bytes_stream();
 
     while let Some(chunk) = stream.next().await {
         let chunk = chunk.map_err(TrackerError::request)?;
 
-        total_bytes += chunk.len();
-        progress.download_progress(total_bytes, &filepath);
+        progress.bytes += chunk.len();
+        progress.elapsed = start.elapsed();
+        listener.download_progress(&progress);
 
+        hasher.update(&chunk);
         io::copy(&mut chunk.as_ref(), &mut dest)
             .await
             .map_err(TrackerError::io)?;
     }
 
-    Ok(())
+    dest.flush().await.map_err(TrackerError::io)?;
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Path of the partial file that a download into `filepath` is written to before being complete,
+/// which is `filepath` with `.part` appended to its name.
+fn part_path(filepath: &Path) -> PathBuf {
+    let mut name = filepath.file_name().unwrap_or_default().to_os_string();
+    name.push(".part");
+    filepath.with_file_name(name)
+}
+
+/// Reads the body of `cached` as text.
+async fn read_cached(cached: &CachedResponse) -> errors::Result<String> {
+    fs::read_to_string(&cached.body).await.map_err(|err| {
+        TrackerError::io(err).context(format!(
+            "failed to read cached response `{}`",
+            cached.body.display()
+        ))
+    })
+}
+
+/// Copies the body of `cached` into `dest`, returning the SHA-256 hash of its content.
+async fn restore_cached(cached: &CachedResponse, dest: &Path) -> errors::Result<String> {
+    let content = fs::read(&cached.body).await.map_err(|err| {
+        TrackerError::io(err).context(format!(
+            "failed to read cached response `{}`",
+            cached.body.display()
+        ))
+    })?;
+
+    fs::write(dest, &content).await.map_err(|err| {
+        TrackerError::io(err).context(format!("failed to write file `{}`", dest.display()))
+    })?;
+
+    Ok(format!("{:x}", Sha256::digest(&content)))
 }
 
 fn split_str_to_args(command: &str, story: &Story, config: &Config) -> errors::Result<Vec<String>> {
@@ -54,9 +110,100 @@ fn split_str_to_args(command: &str, story: &Story, config: &Config) -> errors::R
         })
 }
 
+/// A file downloaded by [`AsyncRequester::client_download()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DownloadedFile {
+    /// The format of the file.
+    pub format: DownloadFormat,
+    /// Path to the file.
+    pub path: PathBuf,
+    /// SHA-256 hash of the content, as lowercase hexadecimal.
+    pub hash: String,
+    /// Whether the content was the same as of the last download, leaving the file untouched.
+    pub unchanged: bool,
+}
+
+/// The result of a successful [`AsyncRequester::download()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Downloaded {
+    /// The story was downloaded from Fimfiction into the given files, one for each format.
+    Files(Vec<DownloadedFile>),
+    /// The story was handed to the `exec` command, which exited with the given status.
+    Command(ExitStatus),
+}
+
+impl Downloaded {
+    /// Stores the hashes of the downloaded files into `story`, for the next download to compare
+    /// against.
+    pub fn record_hashes(&self, story: &mut Story) {
+        if let Downloaded::Files(files) = self {
+            for file in files {
+                story.hashes.insert(file.format, file.hash.clone());
+            }
+        }
+    }
+
+    /// Stores into `story` that it was downloaded at `downloaded_at`, with the hashes of the
+    /// downloaded files as in [`record_hashes()`](Downloaded::record_hashes) and the path to the
+    /// one of the main format, if any.
+    pub fn record_download(&self, story: &mut Story, downloaded_at: DateTime<Utc>) {
+        self.record_hashes(story);
+        story.last_downloaded_at = Some(downloaded_at);
+        story.last_download_path = match self {
+            Downloaded::Files(files) => files.first().map(|file| file.path.clone()),
+            Downloaded::Command(_) => None,
+        };
+    }
+}
+
+/// Builds the [`Client`](reqwest::Client) used by [`AsyncRequester::new()`], with the timeouts,
+/// user agent, proxy and headers of `config`.
+///
+/// # Errors
+///
+/// If `config.proxy` isn't a valid URL, any of `config.headers` isn't a valid header or the
+/// client itself can't be built.
+pub fn build_client(config: &Config) -> errors::Result<reqwest::Client> {
+    let mut headers = HeaderMap::with_capacity(config.headers.len());
+    for (name, value) in config.headers.iter() {
+        let name = HeaderName::from_bytes(name.as_bytes()).map_err(|err| {
+            TrackerError::custom(err).context(format!("invalid header name `{}`", name))
+        })?;
+        let value = HeaderValue::from_str(value).map_err(|err| {
+            TrackerError::custom(err).context(format!("invalid value for header `{}`", name))
+        })?;
+        headers.insert(name, value);
+    }
+
+    let mut builder = reqwest::Client::builder()
+        .user_agent(config.user_agent.as_str())
+        .default_headers(headers);
+
+    if config.request_timeout > 0 {
+        builder = builder.timeout(Duration::from_secs(config.request_timeout));
+    }
+
+    if config.connect_timeout > 0 {
+        builder = builder.connect_timeout(Duration::from_secs(config.connect_timeout));
+    }
+
+    if let Some(proxy) = config.proxy.as_ref() {
+        let proxy = reqwest::Proxy::all(proxy).map_err(|err| {
+            TrackerError::request(err).context(format!("invalid proxy `{}`", proxy))
+        })?;
+        builder = builder.proxy(proxy);
+    }
+
+    builder
+        .build()
+        .map_err(|err| TrackerError::request(err).context("failed to build the HTTP client"))
+}
+
 /// An asynchronous story downloader.
 ///
-/// Makes use of an asynchronous [`Client`](reqwest::Client) for all of its requests.
+/// Makes use of an asynchronous [`Client`](reqwest::Client) for all of its requests, which are
+/// limited to `config.requests_per_second` and retried up to `config.max_attempts` times when
+/// they fail with a transient error.
 ///
 /// ```no_run
 /// # use tokio;
@@ -67,7 +214,7 @@ fn split_str_to_args(command: &str, story: &Story, config: &Config) -> errors::R
 /// use fimfic_tracker::downloader::{AsyncRequester, SilentListener};
 /// # let config = Config::default();
 ///
-/// let requester = AsyncRequester::new(config, SilentListener);
+/// let requester = AsyncRequester::new(config, SilentListener)?;
 ///
 /// // Requesting "The Moon's Apprentice" by Forthwith
 /// let story = requester.get_story_response(196256).await?;
@@ -85,107 +232,378 @@ where
     client: reqwest::Client,
     config: Config,
     progress: P,
+    limiter: RateLimiter,
+    retry: RetryPolicy,
 }
 
 impl<P> AsyncRequester<P>
 where
     P: ProgressListener,
 {
-    /// Constructs a new [`AsyncRequester`].
-    pub fn new(config: Config, progress: P) -> Self {
+    /// Constructs a new [`AsyncRequester`], with a client configured as in [`build_client()`].
+    ///
+    /// # Errors
+    ///
+    /// According to [`build_client()`].
+    pub fn new(config: Config, progress: P) -> errors::Result<Self> {
+        let client = build_client(&config)?;
+        Ok(AsyncRequester::with_client(config, client, progress))
+    }
+
+    /// Constructs a new [`AsyncRequester`] that makes its requests with `client`, ignoring the
+    /// timeouts, user agent, proxy and headers of `config`.
+    pub fn with_client(config: Config, client: reqwest::Client, progress: P) -> Self {
         AsyncRequester {
-            client: reqwest::Client::new(),
+            client,
+            limiter: RateLimiter::new(config.requests_per_second),
+            retry: RetryPolicy::new(&config),
             config,
             progress,
         }
     }
 
+    /// The [`ProgressListener`] that is told about the progress of the requester.
+    pub(crate) fn listener(&self) -> &P {
+        &self.progress
+    }
+
+    /// Sends `request` once the rate limit allows it, failing on responses with a status that
+    /// is worth retrying.
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response, Failure> {
+        self.limiter.wait().await;
+
+        let res = request.send().await.map_err(TrackerError::request)?;
+        if !retry::is_transient_status(res.status()) {
+            return Ok(res);
+        }
+
+        let retry_after = retry::retry_after(res.headers());
+        let error = res
+            .error_for_status()
+            .expect_err("response should have an error status");
+
+        Err(Failure {
+            error: TrackerError::request(error),
+            retry_after,
+        })
+    }
+
+    /// The response kept in the cache for `url`, if `config.cache` or `config.offline` are
+    /// enabled.
+    async fn cached(&self, url: &Url, config: &Config) -> Option<CachedResponse> {
+        if config.cache || config.offline {
+            cache::lookup(url, config).await
+        } else {
+            None
+        }
+    }
+
+    /// Sends a request for `url`, made conditional on the response having changed since it was
+    /// kept as `cached`.
+    async fn send_get(
+        &self,
+        url: &Url,
+        cached: Option<&CachedResponse>,
+    ) -> Result<reqwest::Response, Failure> {
+        let mut request = self.client.get(url.clone());
+        if let Some(cached) = cached {
+            request = request.headers(cached.conditional_headers());
+        }
+
+        self.send(request).await
+    }
+
     /// Requests the [`StoryResponse`] of the given Fimfiction story ID.
+    ///
+    /// With `config.cache` enabled, the response is kept in the [`cache`] and the request is
+    /// answered with it when Fimfiction says that it didn't change. With `config.offline`
+    /// enabled, it's always answered with it instead of being made.
+    ///
+    /// # Errors
+    ///
+    /// Besides the request errors, if `config.offline` is enabled and there is no response kept
+    /// for the story, with [`ErrorKind::Offline`](crate::errors::ErrorKind::Offline).
     pub async fn get_story_response(&self, id: Id) -> errors::Result<StoryResponse> {
-        let url = Url::parse_with_params(
-            "https://www.fimfiction.net/api/story.php",
-            &[("story", id.to_string())],
-        )
-        .expect("Fimficiton API URL parse failed");
-
-        let json = self
-            .client
-            .get(url)
-            .send()
-            .await
-            .map_err(TrackerError::request)?
-            .text()
+        let mut url = self.config.site_url("api/story.php");
+        url.query_pairs_mut().append_pair("story", &id.to_string());
+        let url = &url;
+        let cached = &self.cached(url, &self.config).await;
+
+        if self.config.offline {
+            let json = match cached {
+                Some(cached) => read_cached(cached).await?,
+                None => return Err(TrackerError::offline(url.as_str())),
+            };
+
+            return fimfiction_api::from_str(&json)
+                .map_err(|err| TrackerError::unexpected_response(err, id, json));
+        }
+
+        self.retry
+            .run(id, &self.progress, || async move {
+                let res = self.send_get(url, cached.as_ref()).await?;
+
+                let json = match cached {
+                    Some(cached) if res.status() == StatusCode::NOT_MODIFIED => {
+                        read_cached(cached).await?
+                    }
+                    _ => {
+                        let cacheable = self.config.cache && res.status().is_success();
+                        let headers = res.headers().clone();
+                        let json = res.text().await.map_err(|err| {
+                            TrackerError::request(err)
+                                .context("couldn't decode the Fimfiction API response body")
+                        })?;
+
+                        if cacheable {
+                            if let Err(err) =
+                                cache::store(url, &headers, json.as_bytes(), &self.config).await
+                            {
+                                warn!("{}", err);
+                            }
+                        }
+
+                        json
+                    }
+                };
+
+                fimfiction_api::from_str(&json)
+                    .map_err(|err| TrackerError::unexpected_response(err, id, json).into())
+            })
             .await
-            .map_err(|err| {
-                TrackerError::request(err)
-                    .context("couldn't decode the Fimfiction API response body")
-            })?;
+    }
 
-        fimfiction_api::from_str(&json)
-            .map_err(|err| TrackerError::unexpected_response(err, id, json))
+    /// Requests the [`StoryResponse`] of every ID in `ids` with
+    /// [`AsyncRequester::get_story_response()`], keeping up to `config.max_concurrent_requests`
+    /// requests in-flight at the same time.
+    ///
+    /// The results are returned in the same order as `ids`, paired with their ID.
+    pub async fn get_story_responses<I>(&self, ids: I) -> Vec<(Id, errors::Result<StoryResponse>)>
+    where
+        I: IntoIterator<Item = Id>,
+    {
+        stream::iter(ids)
+            .map(|id| async move { (id, self.get_story_response(id).await) })
+            .buffered(self.config.max_concurrent_requests.max(1))
+            .collect()
+            .await
     }
 
-    /// Downloads `story` from Fimfiction into the download directory in the
-    /// [`DownloadFormat`](crate::DownloadFormat) specified in the given [`Config`].
+    /// Downloads `story` from Fimfiction into the download directory in each of the
+    /// [`DownloadFormat`]s specified in the given [`Config`], one after the other.
+    ///
+    /// Both are taken from [`Config::for_story()`], so the overrides of `story` apply.
+    ///
+    /// Each file is placed according to `config.filename_template` as described in
+    /// [`download_path()`], creating any missing directory, and their paths are returned in the
+    /// order of the formats. The story is first written into a `.part` file next to that path,
+    /// which replaces the previous file only once the download is complete and is removed
+    /// otherwise. With `config.archive` enabled, the previous file is moved into the
+    /// [`archive`](crate::archive) instead of being overwritten.
+    ///
+    /// The content is hashed while downloading, so when it is the same as the one recorded for
+    /// that format in `story.hashes` the file is left untouched, as told by
+    /// [`DownloadedFile::unchanged`]. Use [`Downloaded::record_hashes()`] to keep them.
+    ///
+    /// If the request or the download itself fail with a transient error, the download of that
+    /// format is started over.
     ///
-    /// Uses a sanitized `{TITLE}.{FORMAT}` as the filename.
+    /// With `config.cache` enabled, each download is kept in the [`cache`] and the request is
+    /// answered with it when Fimfiction says that it didn't change, without transferring it again.
+    /// With `config.offline` enabled, it's always answered with it instead of being made.
     ///
     /// # Errors
     ///
-    /// They are returned according to tokio's [`fs::OpenOptions::open()`] and [`io::copy()`].
-    pub async fn client_download(&self, story: &Story) -> errors::Result<()> {
-        let req = self
-            .client
-            .get(download_url_format(story, self.config.download_format));
-
-        let filename =
-            sanitize_filename(format!("{}.{}", &story.title, self.config.download_format));
-        let filepath = self.config.download_dir.join(filename);
-
-        let res = req
-            .send()
-            .await
-            .map_err(|err| TrackerError::request(err).context("failed to start story download"))?;
+    /// They are returned according to tokio's [`fs::create_dir_all()`],
+    /// [`fs::OpenOptions::open()`], [`io::copy()`] and [`fs::rename()`], stopping on the first
+    /// format that fails. With `config.offline` enabled, a format without a download kept in the
+    /// cache fails with [`ErrorKind::Offline`](crate::errors::ErrorKind::Offline).
+    pub async fn client_download(&self, story: &Story) -> errors::Result<Vec<DownloadedFile>> {
+        let config = self.config.for_story(story);
+        let mut files = Vec::with_capacity(config.download_formats.len());
+
+        for format in config.download_formats.iter().copied() {
+            let file = match self.format_download(story, format, &config).await {
+                Ok(file) => file,
+                Err(err) => {
+                    self.progress.download_failed(story, &err);
+                    return Err(err);
+                }
+            };
+            if file.unchanged {
+                self.progress
+                    .unchanged_format_download(story, format, &file.path);
+            } else {
+                self.progress
+                    .successfull_format_download(story, format, &file.path);
+            }
+            files.push(file);
+        }
 
-        let dest = fs::OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(&filepath)
-            .await
-            .map_err(|err| {
+        self.progress.successfull_client_download(story);
+
+        Ok(files)
+    }
+
+    /// Downloads `story` from Fimfiction in `format` into the path given by `config`, leaving the
+    /// file in that path untouched if the content hasn't changed since the last download.
+    async fn format_download(
+        &self,
+        story: &Story,
+        format: DownloadFormat,
+        config: &Config,
+    ) -> errors::Result<DownloadedFile> {
+        let url = &download_url_format(story, format, config);
+
+        let filepath = &download_path(story, format, config);
+
+        if let Some(parent) = filepath.parent() {
+            fs::create_dir_all(parent).await.map_err(|err| {
                 TrackerError::io(err)
-                    .context(format!("failed to create file `{}`", filepath.display()))
+                    .context(format!("failed to create directory `{}`", parent.display()))
             })?;
+        }
 
-        download(res, dest, filepath.display(), &self.progress)
-            .await
-            .map_err(|err| {
-                err.context(format!(
-                    "failed to download story to `{}`",
-                    filepath.display()
-                ))
-            })?;
+        let part_filepath = &part_path(filepath);
+        let cached = &self.cached(url, config).await;
 
-        self.progress.successfull_client_download(story);
+        let hash = match cached {
+            Some(cached) if config.offline => restore_cached(cached, part_filepath).await?,
+            None if config.offline => return Err(TrackerError::offline(url.as_str())),
+            _ => {
+                self.request_download(story, url, filepath, cached.as_ref(), config)
+                    .await?
+            }
+        };
 
-        Ok(())
+        let unchanged = story.hashes.get(&format) == Some(&hash)
+            && fs::try_exists(filepath).await.unwrap_or(false);
+
+        if unchanged {
+            let _ = fs::remove_file(part_filepath).await;
+            return Ok(DownloadedFile {
+                format,
+                path: filepath.clone(),
+                hash,
+                unchanged,
+            });
+        }
+
+        // Archiving reads and writes the archive directory with blocking calls.
+        let replaced = {
+            let (part_path, path) = (part_filepath.clone(), filepath.clone());
+            let (story, config) = (story.clone(), config.clone());
+            task::spawn_blocking(move || replace_file(&part_path, &path, &story, &config))
+                .await
+                .map_err(|err| {
+                    TrackerError::custom(err)
+                        .context(format!("failed to move `{}`", part_filepath.display()))
+                })
+                .and_then(|result| result)
+        };
+
+        if let Err(err) = replaced {
+            let _ = fs::remove_file(part_filepath).await;
+            return Err(err);
+        }
+
+        Ok(DownloadedFile {
+            format,
+            path: filepath.clone(),
+            hash,
+            unchanged,
+        })
+    }
+
+    /// Downloads `story` from `url` into the partial file of `filepath`, as a request
+    /// conditional on `cached`, returning the hash of the content.
+    async fn request_download(
+        &self,
+        story: &Story,
+        url: &Url,
+        filepath: &Path,
+        cached: Option<&CachedResponse>,
+        config: &Config,
+    ) -> errors::Result<String> {
+        let part_filepath = &part_path(filepath);
+
+        self.retry
+            .run(story.id, &self.progress, || async move {
+                let res = self
+                    .send_get(url, cached)
+                    .await
+                    .map_err(|failure| failure.context("failed to start story download"))?;
+
+                if let Some(cached) = cached {
+                    if res.status() == StatusCode::NOT_MODIFIED {
+                        return Ok(restore_cached(cached, part_filepath).await?);
+                    }
+                }
+
+                let cacheable = config.cache && res.status().is_success();
+                let headers = res.headers().clone();
+
+                let dest = fs::OpenOptions::new()
+                    .create(true)
+                    .write(true)
+                    .truncate(true)
+                    .open(part_filepath)
+                    .await
+                    .map_err(|err| {
+                        TrackerError::io(err).context(format!(
+                            "failed to create file `{}`",
+                            part_filepath.display()
+                        ))
+                    })?;
+
+                match download(res, dest, story, filepath.display(), &self.progress).await {
+                    Ok(hash) => {
+                        if cacheable {
+                            if let Err(err) =
+                                cache::store_file(url, &headers, part_filepath, config).await
+                            {
+                                warn!("{}", err);
+                            }
+                        }
+                        Ok(hash)
+                    }
+                    Err(err) => {
+                        let _ = fs::remove_file(part_filepath).await;
+                        Err(err
+                            .context(format!(
+                                "failed to download story to `{}`",
+                                filepath.display()
+                            ))
+                            .into())
+                    }
+                }
+            })
+            .await
     }
 
     /// Expands shell-like variables present in `command` and then executes it with tokio's
-    /// [`Command`], taking into account the value of `config.quiet`.
+    /// [`Command`], taking into account the value of `config.quiet`, returning its exit status.
     ///
-    /// More info on said expansion in [`env_with_command_context()`].
+    /// More info on said expansion in [`env_with_command_context()`], which is done with the
+    /// configuration from [`Config::for_story()`].
     ///
     /// # Errors
     ///
     /// Besides failing on a badly written `command` it can error according to
-    /// [`Command::status()`].
-    pub async fn exec_download<S>(&self, command: S, story: &Story) -> errors::Result<()>
+    /// [`Command::status()`]. With `config.offline` enabled, it fails with
+    /// [`ErrorKind::Offline`](crate::errors::ErrorKind::Offline) without executing `command`, as
+    /// it can't be known to not make requests.
+    pub async fn exec_download<S>(&self, command: S, story: &Story) -> errors::Result<ExitStatus>
     where
         S: AsRef<str>,
     {
-        let args = split_str_to_args(command.as_ref(), story, &self.config)
+        let config = self.config.for_story(story);
+        if config.offline {
+            return Err(TrackerError::offline(story.url_in(&config))
+                .context("download commands aren't executed while offline"));
+        }
+
+        let args = split_str_to_args(command.as_ref(), story, &config)
             .map_err(|err| err.context("exec command should mimic a POSIX shell command"))?;
 
         let mut command = Command::new(&args[0]);
@@ -193,7 +611,7 @@ where
             command.args(&args[1..]);
         }
 
-        if self.config.quiet {
+        if config.quiet {
             command.stdout(Stdio::null()).stderr(Stdio::null());
         }
 
@@ -204,6 +622,8 @@ where
         })?;
 
         if !status.success() {
+            self.progress.command_failed(story, status);
+
             let err = match status.code() {
                 Some(code) => TrackerError::custom(format!(
                     "command process exited with status code {}",
@@ -216,18 +636,22 @@ where
         }
 
         self.progress.successfull_command_execution(story);
-        Ok(())
+        Ok(status)
     }
 
-    /// Downloads the given `story` from Fimfiction taking into account the given [`Config`].
+    /// Downloads the given `story` from Fimfiction taking into account the given [`Config`], with
+    /// the overrides of `story` applied as in [`Config::for_story()`].
     /// Where if `config.exec()`:
     /// - Is `None`, passes `story` through [`AsyncRequester::client_download()`].
     /// - Is `Some(exec)`, passes `story` and the present `exec` command through
     /// [`AsyncRequester::exec_download()`].
-    pub async fn download(&self, story: &Story) -> errors::Result<()> {
-        match self.config.exec.as_ref() {
-            Some(exec) => self.exec_download(exec, story).await,
-            None => self.client_download(story).await,
+    pub async fn download(&self, story: &Story) -> errors::Result<Downloaded> {
+        match self.config.for_story(story).exec.as_ref() {
+            Some(exec) => self
+                .exec_download(exec, story)
+                .await
+                .map(Downloaded::Command),
+            None => self.client_download(story).await.map(Downloaded::Files),
         }
     }
 }
@@ -241,10 +665,45 @@ mod test {
     use crate::config::ConfigBuilder;
     use crate::StoryStatus;
 
+    #[test]
+    fn client_from_config() {
+        let config: Config = ConfigBuilder::new()
+            .request_timeout(0)
+            .user_agent("my-tracker/1.0")
+            .proxy("socks5://127.0.0.1:9050")
+            .headers([("Accept-Language", "en")])
+            .into();
+        assert!(build_client(&config).is_ok());
+
+        let config: Config = ConfigBuilder::new().proxy("not a proxy").into();
+        assert!(build_client(&config).is_err());
+
+        let config: Config = ConfigBuilder::new()
+            .headers([("Invalid Name", "value")])
+            .into();
+        assert!(build_client(&config).is_err());
+
+        let config: Config = ConfigBuilder::new()
+            .headers([("X-Value", "line\nbreak")])
+            .into();
+        assert!(build_client(&config).is_err());
+    }
+
+    #[test]
+    fn partial_download_path() {
+        assert_eq!(
+            part_path(Path::new("/path/to/download-dir/A Title.epub")),
+            Path::new("/path/to/download-dir/A Title.epub.part")
+        );
+        assert_eq!(
+            part_path(Path::new("A Title (165).html")),
+            Path::new("A Title (165).html.part")
+        );
+    }
+
     #[test]
     fn argument_split() {
         let story = Story {
-            id: 0,
             title: "A \"Story\" Title".into(),
             author: "An \"Author\"".into(),
             chapter_count: 10,
@@ -254,6 +713,7 @@ mod test {
                 .single()
                 .expect("DateTime should be valid and with a single result"),
             status: StoryStatus::Hiatus,
+            ..Story::default_for_test(0)
         };
 
         let config: Config = ConfigBuilder::new()
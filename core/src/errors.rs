@@ -4,6 +4,7 @@ use std::{error::Error, fmt, io};
 #[doc(inline)]
 pub use fimfiction_api::StoryError;
 
+use crate::migration::TRACKER_FORMAT_VERSION;
 use crate::story::Id;
 
 /// An alias of [`Result`] for all of its instances across the crate.
@@ -68,6 +69,28 @@ pub enum ErrorKind {
         action: Action,
         /// The error being thrown.
         error: serde_json::Error,
+        /// Path to the backup of the tracker file, if it was loaded in its place but couldn't be
+        /// understood either.
+        backup: Option<String>,
+    },
+    /// The tracker file was written with a more recent version of its format than the supported
+    /// one.
+    UnsupportedTrackerVersion {
+        /// Path to the tracker file.
+        path: String,
+        /// The version of its format.
+        version: u64,
+    },
+    /// The tracker file is locked by another process.
+    TrackerLocked {
+        /// Path to the locked tracker file.
+        path: String,
+    },
+    /// A request was needed while in offline mode, without a response kept in the
+    /// [`cache`](crate::cache) to answer it with, or a download command was about to be executed.
+    Offline {
+        /// The URL that would have been requested, or of the story the command was for.
+        url: String,
     },
     /// A custom error.
     Custom(String),
@@ -99,6 +122,20 @@ impl TrackerError {
         self
     }
 
+    /// Adds `context` after the already present one, if any.
+    #[cfg(feature = "downloader")]
+    pub(crate) fn append_context<C>(mut self, context: C) -> Self
+    where
+        C: Into<String>,
+    {
+        let context = context.into();
+        self.context = Some(match self.context.take() {
+            Some(previous) => format!("{}, {}", previous, context),
+            None => context,
+        });
+        self
+    }
+
     /// Constructs a [`TrackerError`] of kind [`Io`](ErrorKind::Io).
     pub fn io(err: io::Error) -> Self {
         TrackerError::with(ErrorKind::Io(err))
@@ -141,9 +178,38 @@ impl TrackerError {
             path: path.into(),
             action,
             error,
+            backup: None,
+        })
+    }
+
+    /// Constructs a [`TrackerError`] of kind
+    /// [`UnsupportedTrackerVersion`](ErrorKind::UnsupportedTrackerVersion).
+    pub fn unsupported_tracker_version<T>(path: T, version: u64) -> Self
+    where
+        T: Into<String>,
+    {
+        TrackerError::with(ErrorKind::UnsupportedTrackerVersion {
+            path: path.into(),
+            version,
         })
     }
 
+    /// Constructs a [`TrackerError`] of kind [`TrackerLocked`](ErrorKind::TrackerLocked).
+    pub fn tracker_locked<T>(path: T) -> Self
+    where
+        T: Into<String>,
+    {
+        TrackerError::with(ErrorKind::TrackerLocked { path: path.into() })
+    }
+
+    /// Constructs a [`TrackerError`] of kind [`Offline`](ErrorKind::Offline).
+    pub fn offline<T>(url: T) -> Self
+    where
+        T: Into<String>,
+    {
+        TrackerError::with(ErrorKind::Offline { url: url.into() })
+    }
+
     /// Constructs a [`TrackerError`] of kind [`Custom`](ErrorKind::Custom).
     pub fn custom<M>(message: M) -> Self
     where
@@ -193,6 +259,7 @@ impl fmt::Display for TrackerError {
                 ref path,
                 ref action,
                 ref error,
+                ref backup,
             } => {
                 write!(f, "error in tracker format")?;
 
@@ -207,6 +274,27 @@ impl fmt::Display for TrackerError {
                 };
 
                 write!(f, ": {}", error)?;
+
+                if let Some(backup) = backup {
+                    write!(f, " (its backup `{}` couldn't be loaded either)", backup)?;
+                }
+            }
+            ErrorKind::UnsupportedTrackerVersion { path, version } => {
+                write!(
+                    f,
+                    "tracker file `{}` has format version {}, but only up to version {} is supported",
+                    path, version, TRACKER_FORMAT_VERSION
+                )?;
+            }
+            ErrorKind::TrackerLocked { path } => {
+                write!(f, "tracker file `{}` is locked by another process", path)?;
+            }
+            ErrorKind::Offline { url } => {
+                write!(
+                    f,
+                    "`{}` can't be requested while offline and isn't in the cache",
+                    url
+                )?;
             }
             ErrorKind::Custom(err) => {
                 write!(f, "{}", err)?;
@@ -221,6 +309,7 @@ impl Error for TrackerError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         match self.kind {
             ErrorKind::Io(ref err) => Some(err),
+            #[cfg(feature = "downloader")]
             ErrorKind::Request(ref err) => Some(err),
             ErrorKind::UnexpectedResponse { ref error, .. } => Some(error),
             ErrorKind::ConfigParsing(ref source) => Some(match source {
@@ -228,7 +317,11 @@ impl Error for TrackerError {
                 ConfigSource::Env(ref err) => err,
             }),
             ErrorKind::TrackerFormat { ref error, .. } => Some(error),
-            ErrorKind::BadStoryComparison { .. } | ErrorKind::Custom(_) => None,
+            ErrorKind::BadStoryComparison { .. }
+            | ErrorKind::UnsupportedTrackerVersion { .. }
+            | ErrorKind::TrackerLocked { .. }
+            | ErrorKind::Offline { .. }
+            | ErrorKind::Custom(_) => None,
         }
     }
 }
@@ -0,0 +1,286 @@
+//! History of the changes found on the tracked stories.
+//!
+//! Every change found while checking for updates can be recorded with [`record()`] as a
+//! [`HistoryEntry`] into the history file next to the tracker file, see [`history_path()`],
+//! which is only ever appended to with one JSON object per line.
+//! ```no_run
+//! # use fimfic_tracker::Result;
+//! # fn main() -> Result<()> {
+//! use fimfic_tracker::{history, Config};
+//! # let config = Config::default();
+//!
+//! for entry in history::read(&config)?.iter().filter(|entry| entry.id == 196256) {
+//!     println!("{}: {:?}", entry.detected_at, entry.changes.update);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::checker::StoryCheck;
+use crate::config::Config;
+use crate::errors::{self, TrackerError};
+use crate::story::{Id, StoryUpdate};
+use crate::utils::with_suffix;
+use crate::StoryStatus;
+
+/// A value of a story that changed, from `before` to `after`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Change<T> {
+    /// The value before the change.
+    pub before: T,
+    /// The value after the change.
+    pub after: T,
+}
+
+impl<T: PartialEq> Change<T> {
+    fn new(before: T, after: T) -> Option<Self> {
+        if before == after {
+            None
+        } else {
+            Some(Change { before, after })
+        }
+    }
+}
+
+/// The changes found on a story by a check.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct StoryChanges {
+    /// The update found, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub update: Option<StoryUpdate>,
+    /// The change of title, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title_change: Option<Change<String>>,
+    /// The change of author, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author_change: Option<Change<String>>,
+    /// The change of completion status, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status_change: Option<Change<StoryStatus>>,
+}
+
+impl StoryChanges {
+    /// Constructs the [`StoryChanges`] of `check`, if it [has any](StoryCheck::has_changes).
+    pub fn from_check(check: &StoryCheck) -> Option<Self> {
+        if !check.has_changes() {
+            return None;
+        }
+
+        let (before, after) = (&check.before, &check.after);
+        Some(StoryChanges {
+            update: check.update.clone(),
+            title_change: Change::new(before.title.clone(), after.title.clone()),
+            author_change: Change::new(before.author.clone(), after.author.clone()),
+            status_change: Change::new(before.status, after.status),
+        })
+    }
+}
+
+/// The changes found on a story by a check, as recorded in the history file.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HistoryEntry {
+    /// ID of the story.
+    pub id: Id,
+    /// Title of the story after the changes.
+    pub title: String,
+    /// When the changes were found.
+    pub detected_at: DateTime<Utc>,
+    /// The changes found.
+    #[serde(flatten)]
+    pub changes: StoryChanges,
+}
+
+impl HistoryEntry {
+    /// Constructs the [`HistoryEntry`] of the changes in `check` found at `detected_at`, if it
+    /// [has any](StoryCheck::has_changes).
+    pub fn from_check(check: &StoryCheck, detected_at: DateTime<Utc>) -> Option<Self> {
+        StoryChanges::from_check(check).map(|changes| HistoryEntry {
+            id: check.id(),
+            title: check.after.title.clone(),
+            detected_at,
+            changes,
+        })
+    }
+}
+
+/// Path to the history file, the path of `config.tracker_file` with a `.history.jsonl` suffix.
+pub fn history_path(config: &Config) -> PathBuf {
+    with_suffix(&config.tracker_file, ".history.jsonl")
+}
+
+/// The entries in the history file, from the oldest to the most recent.
+///
+/// The lines that aren't a [`HistoryEntry`], as one that was cut short when written, are skipped
+/// with a warning.
+///
+/// # Errors
+///
+/// If the history file exists but can't be read.
+pub fn read(config: &Config) -> errors::Result<Vec<HistoryEntry>> {
+    let path = history_path(config);
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => {
+            return Err(
+                TrackerError::io(err).context(format!("failed to read file `{}`", path.display()))
+            )
+        }
+    };
+
+    Ok(contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .filter_map(|(index, line)| match serde_json::from_str(line) {
+            Ok(entry) => Some(entry),
+            Err(err) => {
+                warn!(
+                    "Skipping line {} of history file `{}`: {}",
+                    index + 1,
+                    path.display(),
+                    err
+                );
+                None
+            }
+        })
+        .collect())
+}
+
+/// Appends the changes of `checks` into the history file as found at `detected_at`, returning
+/// the amount of entries recorded.
+///
+/// The changes that are the same as the last ones recorded for that story are skipped, so
+/// checking again an update that wasn't saved into the tracker file doesn't record it twice.
+///
+/// # Errors
+///
+/// If the history file can't be read, as in [`read()`], or written.
+pub fn record<'a, I>(
+    checks: I,
+    detected_at: DateTime<Utc>,
+    config: &Config,
+) -> errors::Result<usize>
+where
+    I: IntoIterator<Item = &'a StoryCheck>,
+{
+    let mut last_changes: HashMap<Id, StoryChanges> = read(config)?
+        .into_iter()
+        .map(|entry| (entry.id, entry.changes))
+        .collect();
+    let mut new_entries: Vec<HistoryEntry> = Vec::new();
+
+    for entry in checks
+        .into_iter()
+        .filter_map(|check| HistoryEntry::from_check(check, detected_at))
+    {
+        if last_changes.get(&entry.id) != Some(&entry.changes) {
+            last_changes.insert(entry.id, entry.changes.clone());
+            new_entries.push(entry);
+        }
+    }
+
+    if new_entries.is_empty() {
+        return Ok(0);
+    }
+
+    let path = history_path(config);
+    let map_err = |err| {
+        TrackerError::io(err).context(format!("failed to write into file `{}`", path.display()))
+    };
+
+    let mut lines = String::new();
+    for entry in &new_entries {
+        lines.push_str(&serde_json::to_string(entry).expect("history entries should serialize"));
+        lines.push('\n');
+    }
+
+    fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| file.write_all(lines.as_bytes()))
+        .map_err(map_err)?;
+
+    Ok(new_entries.len())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use tempdir::TempDir;
+
+    use crate::config::{ConfigBuilder, SensibilityLevel};
+    use crate::story::Story;
+
+    fn story(chapter_count: u64, status: StoryStatus) -> Story {
+        Story {
+            chapter_count,
+            status,
+            ..Story::default_for_test(165)
+        }
+    }
+
+    #[test]
+    fn records_changes_once() {
+        let tmp_dir = TempDir::new("fft-history").expect("failed to create temp dir");
+        let config: Config = ConfigBuilder::new()
+            .tracker_file(tmp_dir.path().join("track-data.json").to_string_lossy())
+            .into();
+        let check = |before, after| {
+            StoryCheck::new(before, after, SensibilityLevel::OnlyChapters)
+                .expect("stories should have the same ID")
+        };
+
+        let unchanged = check(
+            story(5, StoryStatus::Incomplete),
+            story(5, StoryStatus::Incomplete),
+        );
+        let updated = check(
+            story(5, StoryStatus::Incomplete),
+            story(6, StoryStatus::Complete),
+        );
+        let now = Utc::now();
+
+        assert!(read(&config).unwrap().is_empty());
+        assert_eq!(record([&unchanged], now, &config).unwrap(), 0);
+        assert!(!history_path(&config).exists());
+
+        assert_eq!(record([&updated, &updated], now, &config).unwrap(), 1);
+        assert_eq!(record([&updated], Utc::now(), &config).unwrap(), 0);
+
+        // A line cut short is skipped.
+        fs::OpenOptions::new()
+            .append(true)
+            .open(history_path(&config))
+            .and_then(|mut file| file.write_all(b"{\"id\":165,\"ti"))
+            .unwrap();
+
+        let entries = read(&config).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].detected_at, now);
+        assert_eq!(
+            entries[0].changes.update,
+            Some(StoryUpdate::Chapters {
+                before: 5,
+                after: 6
+            })
+        );
+        assert_eq!(
+            entries[0].changes.status_change,
+            Some(Change {
+                before: StoryStatus::Incomplete,
+                after: StoryStatus::Complete
+            })
+        );
+        assert_eq!(entries[0].changes.title_change, None);
+    }
+}
@@ -36,19 +36,32 @@
 //! While is possible to manually construct a [`Story`] struct, is recommended to create it from
 //! a deserialized [`StoryResponse`] ([`fimfiction_api::from_str()`]).
 //!
+//! Checking for updates is done through the [`checker`] module, which decides what changed and
+//! what should be downloaded according to the [`Config`], while the previous copies kept of the
+//! downloaded stories are found through the [`archive`] module, the responses kept from Fimfiction
+//! through the [`cache`] module and the changes found on them through the [`history`] module.
+//!
 //! # Optional feature
 //!
 //! The `downloader` enables structs to easily create [`StoryResponse`]s and execute downloads for
-//! stories for either synchronous or asynchronous contexts.
+//! stories for either synchronous or asynchronous contexts, along with the functions to request
+//! and check for updates in [`checker`].
 #![warn(missing_docs)]
 #[macro_use]
 extern crate lazy_static;
+#[macro_use]
+extern crate log;
 
+pub mod archive;
+pub mod cache;
+pub mod checker;
 mod config;
 pub mod errors;
+pub mod history;
 
 #[cfg(feature = "downloader")]
 pub mod downloader;
+mod migration;
 pub mod story;
 mod utils;
 
@@ -59,10 +72,11 @@ pub use config::{
 pub use errors::{Result, TrackerError};
 #[doc(inline)]
 pub use fimfiction_api::{Story as StoryResponse, StoryStatus};
+pub use migration::{Migration, TRACKER_FORMAT_VERSION};
 #[doc(inline)]
-pub use story::{Id, Story, StoryUpdate};
+pub use story::{Id, Story, StoryOverrides, StoryUpdate};
 #[doc(inline)]
 pub use utils::{
-    default_user_config_file, default_user_tracker_file, download_url_format,
-    env_with_command_context, StoryData,
+    default_user_cache_dir, default_user_config_file, default_user_tracker_file, download_path,
+    download_url_format, env_with_command_context, write, StoryData,
 };
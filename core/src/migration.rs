@@ -0,0 +1,201 @@
+//! Versioning and migration of the track data file format.
+//!
+//! The track data file is stored as an envelope with the version of its format:
+//! ```json
+//! {"version": 2, "stories": [...]}
+//! ```
+//!
+//! Older layouts are identified and upgraded step by step to [`TRACKER_FORMAT_VERSION`]:
+//! - Version `0`, from the legacy version: an object with the story IDs as keys and the stories
+//!   as values.
+//! - Version `1`: a bare array of stories.
+use serde::de::Error as _;
+use serde_json::{Map, Value};
+
+use crate::errors::{self, Action, TrackerError};
+
+/// The version of the track data file format written by this version of the crate.
+pub const TRACKER_FORMAT_VERSION: u64 = 2;
+
+/// A migration step applied to the contents of a track data file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Migration {
+    /// The version of the format before the migration.
+    pub from: u64,
+    /// The version of the format after the migration.
+    pub to: u64,
+    /// What changed in the format.
+    pub description: &'static str,
+}
+
+type Step = fn(Value) -> Result<Value, String>;
+
+/// Migration steps, where the step in index `n` upgrades from version `n` to `n + 1`.
+static STEPS: [(Step, &str); TRACKER_FORMAT_VERSION as usize] = [
+    (
+        legacy_to_array,
+        "converted the object keyed by story ID into an array of stories",
+    ),
+    (
+        array_to_envelope,
+        "wrapped the stories into an envelope with the format version",
+    ),
+];
+
+fn legacy_to_array(value: Value) -> Result<Value, String> {
+    let object = match value {
+        Value::Object(object) => object,
+        _ => return Err("expected an object keyed by story ID".into()),
+    };
+
+    let mut stories = Vec::with_capacity(object.len());
+    for (key, mut story) in object {
+        let id = key
+            .parse::<u32>()
+            .map_err(|_| format!("expected a story ID as key, found `{}`", key))?;
+
+        match story.as_object_mut() {
+            Some(fields) => {
+                fields.entry("id").or_insert_with(|| id.into());
+            }
+            None => return Err(format!("expected an object for the story of ID `{}`", id)),
+        };
+
+        stories.push(story);
+    }
+
+    Ok(Value::Array(stories))
+}
+
+fn array_to_envelope(value: Value) -> Result<Value, String> {
+    let mut envelope = Map::new();
+    envelope.insert("version".into(), 2.into());
+    envelope.insert("stories".into(), value);
+    Ok(Value::Object(envelope))
+}
+
+/// Identifies the version of the format of `value`.
+fn detect_version(value: &Value) -> Result<u64, String> {
+    match value {
+        Value::Array(_) => Ok(1),
+        Value::Object(object) => match object.get("version") {
+            Some(version) => version.as_u64().ok_or_else(|| {
+                format!("expected an unsigned integer as version, found {}", version)
+            }),
+            None => Ok(0),
+        },
+        _ => Err("expected an object or an array as the root of the tracker file".into()),
+    }
+}
+
+/// Upgrades the contents of a track data file to [`TRACKER_FORMAT_VERSION`], returning the
+/// result and the migrations that were applied, in order.
+///
+/// # Errors
+///
+/// - If the version of `value` is more recent than [`TRACKER_FORMAT_VERSION`], with an error of
+///   kind [`UnsupportedTrackerVersion`](errors::ErrorKind::UnsupportedTrackerVersion).
+/// - If `value` doesn't follow the layout of its version, with an error of kind
+///   [`TrackerFormat`](errors::ErrorKind::TrackerFormat).
+pub(crate) fn migrate(path: &str, mut value: Value) -> errors::Result<(Value, Vec<Migration>)> {
+    let format_error = |message: String| {
+        TrackerError::tracker_format(
+            path.to_string(),
+            serde_json::Error::custom(message),
+            Action::Deserializing,
+        )
+    };
+
+    let version = detect_version(&value).map_err(format_error)?;
+    if version > TRACKER_FORMAT_VERSION {
+        return Err(TrackerError::unsupported_tracker_version(path, version));
+    }
+
+    let mut migrations = Vec::new();
+    for (from, (step, description)) in STEPS.iter().enumerate().skip(version as usize) {
+        value = step(value).map_err(format_error)?;
+        migrations.push(Migration {
+            from: from as u64,
+            to: from as u64 + 1,
+            description,
+        });
+    }
+
+    Ok((value, migrations))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use serde_json::json;
+
+    use crate::errors::ErrorKind;
+
+    fn story(id: u32) -> Value {
+        json!({
+            "id": id,
+            "title": "A Story Title",
+            "author": "An Author",
+            "chapter-amt": 2,
+            "words": 10000,
+            "last-update-timestamp": 1607137200,
+            "completion-status": 0
+        })
+    }
+
+    fn versions(migrations: &[Migration]) -> Vec<(u64, u64)> {
+        migrations.iter().map(|m| (m.from, m.to)).collect()
+    }
+
+    #[test]
+    fn migrate_legacy() {
+        let mut first = story(100);
+        first.as_object_mut().unwrap().remove("id");
+        let legacy = json!({ "100": first, "200": story(200) });
+
+        let (value, migrations) = migrate("track-data.json", legacy).unwrap();
+        assert_eq!(versions(&migrations), [(0, 1), (1, 2)]);
+        assert_eq!(
+            value,
+            json!({ "version": 2, "stories": [story(100), story(200)] })
+        );
+    }
+
+    #[test]
+    fn migrate_array() {
+        let (value, migrations) =
+            migrate("track-data.json", json!([story(100), story(200)])).unwrap();
+        assert_eq!(versions(&migrations), [(1, 2)]);
+        assert_eq!(
+            value,
+            json!({ "version": 2, "stories": [story(100), story(200)] })
+        );
+    }
+
+    #[test]
+    fn current_version_is_untouched() {
+        let current = json!({ "version": 2, "stories": [story(100)] });
+        let (value, migrations) = migrate("track-data.json", current.clone()).unwrap();
+        assert!(migrations.is_empty());
+        assert_eq!(value, current);
+    }
+
+    #[test]
+    fn invalid_layouts() {
+        match migrate("track-data.json", json!({ "version": 3, "stories": [] }))
+            .unwrap_err()
+            .kind
+        {
+            ErrorKind::UnsupportedTrackerVersion { version, .. } => assert_eq!(version, 3),
+            _ => unreachable!(),
+        }
+
+        for value in [json!("stories"), json!({ "not-an-id": story(100) })] {
+            match migrate("track-data.json", value).unwrap_err().kind {
+                ErrorKind::TrackerFormat { error, .. } => assert!(error.is_data()),
+                _ => unreachable!(),
+            }
+        }
+    }
+}
@@ -1,8 +1,12 @@
 //! Story storage data (de)serialization.
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+
 use chrono::{offset::Utc, DateTime};
 use fimfiction_api::StoryStatus;
 use serde::{Deserialize, Serialize};
 
+use crate::config::{Config, DownloadFormat, SensibilityLevel};
 use crate::errors::{self, TrackerError};
 use crate::StoryResponse;
 
@@ -31,6 +35,92 @@ pub struct Story {
     /// Story completion status.
     #[serde(rename = "completion-status")]
     pub status: StoryStatus,
+    /// Configuration values that apply only to this story.
+    #[serde(default, skip_serializing_if = "StoryOverrides::is_empty")]
+    pub overrides: StoryOverrides,
+    /// SHA-256 hash of the last file downloaded from Fimfiction in each format, as lowercase
+    /// hexadecimal.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub hashes: BTreeMap<DownloadFormat, String>,
+    /// Datetime of when the story was added to the tracking list, if known.
+    #[serde(
+        default,
+        rename = "added-timestamp",
+        with = "chrono::serde::ts_seconds_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub added_at: Option<DateTime<Utc>>,
+    /// Datetime of the last time the story was successfully checked for updates, if ever.
+    #[serde(
+        default,
+        rename = "last-check-timestamp",
+        with = "chrono::serde::ts_seconds_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub last_checked_at: Option<DateTime<Utc>>,
+    /// Datetime of the last time the story was downloaded, if ever.
+    #[serde(
+        default,
+        rename = "last-download-timestamp",
+        with = "chrono::serde::ts_seconds_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub last_downloaded_at: Option<DateTime<Utc>>,
+    /// Path to the file of the main format the story was last downloaded into, if it was
+    /// downloaded from Fimfiction.
+    #[serde(
+        default,
+        rename = "last-download-path",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub last_download_path: Option<PathBuf>,
+    /// Tags given to the story to organize the tracking list.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub tags: BTreeSet<String>,
+    /// A free-text note about the story.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+/// Configuration values of a [`Story`] that take precedence over the ones in
+/// [`Config`](crate::Config), as resolved by [`Config::for_story()`](crate::Config::for_story).
+///
+/// A `None` value means that the configured one is used.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct StoryOverrides {
+    /// The formats in which to download the story.
+    #[serde(
+        default,
+        deserialize_with = "crate::config::deserialize_formats",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub download_format: Option<Vec<DownloadFormat>>,
+    /// Command to execute instead of downloading the story from Fimfiction, where an empty one
+    /// means downloading it from Fimfiction even if a command is configured.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exec: Option<String>,
+    /// The sensibility level for the updates of the story.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sensibility_level: Option<SensibilityLevel>,
+    /// Path to the directory to download the story into, expanding tilde into home directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub download_dir: Option<String>,
+    /// Whether to only keep track of the updates of the story, without ever downloading it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub skip_download: Option<bool>,
+}
+
+impl StoryOverrides {
+    /// Whether no value is overridden.
+    pub fn is_empty(&self) -> bool {
+        *self == StoryOverrides::default()
+    }
+
+    /// Whether the story should never be downloaded, `false` if not overridden.
+    pub fn skip_download(&self) -> bool {
+        self.skip_download.unwrap_or(false)
+    }
 }
 
 impl From<StoryResponse> for Story {
@@ -43,6 +133,14 @@ impl From<StoryResponse> for Story {
             words: response.words,
             update_datetime: response.date_modified,
             status: response.status,
+            overrides: StoryOverrides::default(),
+            hashes: BTreeMap::new(),
+            added_at: None,
+            last_checked_at: None,
+            last_downloaded_at: None,
+            last_download_path: None,
+            tags: BTreeSet::new(),
+            note: None,
         }
     }
 }
@@ -50,7 +148,11 @@ impl From<StoryResponse> for Story {
 /// Kind of update present in a comparison between two [`Story`] structs.
 ///
 /// Meant to be used as a result of [`Story::compare_to()`].
-#[derive(Debug)]
+///
+/// Serializes with the kind of update in the `on` field, as in
+/// `{"on": "chapters", "before": 5, "after": 6}`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "on", rename_all = "kebab-case")]
 pub enum StoryUpdate {
     /// Story had a chapter update.
     Chapters {
@@ -75,12 +177,79 @@ pub enum StoryUpdate {
     },
 }
 
+impl StoryUpdate {
+    /// Whether the update is relevant enough to be considered under the given
+    /// [`SensibilityLevel`].
+    ///
+    /// A [`Chapters`](StoryUpdate::Chapters) update is always relevant, while a
+    /// [`Words`](StoryUpdate::Words) update requires at least
+    /// [`IncludeWords`](SensibilityLevel::IncludeWords) and a
+    /// [`DateTime`](StoryUpdate::DateTime) update requires
+    /// [`Anything`](SensibilityLevel::Anything).
+    pub fn is_relevant(&self, level: SensibilityLevel) -> bool {
+        match self {
+            StoryUpdate::Chapters { .. } => true,
+            StoryUpdate::Words { .. } => level >= SensibilityLevel::IncludeWords,
+            StoryUpdate::DateTime { .. } => level == SensibilityLevel::Anything,
+        }
+    }
+}
+
+#[cfg(test)]
+impl Story {
+    /// A story of ID `id` with placeholder data on Fimfiction and nothing else tracked, to be
+    /// changed as needed by each test.
+    pub(crate) fn default_for_test(id: Id) -> Story {
+        use chrono::TimeZone;
+
+        Story {
+            id,
+            title: "A Title".into(),
+            author: "An Author".into(),
+            chapter_count: 5,
+            words: 15017,
+            update_datetime: Utc
+                .with_ymd_and_hms(2021, 3, 1, 0, 0, 0)
+                .single()
+                .expect("DateTime should be valid and with a single result"),
+            status: StoryStatus::Incomplete,
+            overrides: StoryOverrides::default(),
+            hashes: BTreeMap::new(),
+            added_at: None,
+            last_checked_at: None,
+            last_downloaded_at: None,
+            last_download_path: None,
+            tags: BTreeSet::new(),
+            note: None,
+        }
+    }
+}
+
 impl Story {
     /// Gets the Fimfiction URL to the story.
     pub fn url(&self) -> String {
         format!("https://www.fimfiction.net/story/{}", self.id)
     }
 
+    /// Gets the URL to the story in `config.base_url`, the same as [`Story::url()`] unless
+    /// configured otherwise.
+    pub fn url_in(&self, config: &Config) -> String {
+        config.site_url(&format!("story/{}", self.id)).into()
+    }
+
+    /// Keeps in `self` what is tracked of `previous` besides its data on Fimfiction: its
+    /// overrides, hashes, tags, note and when it was added, checked and downloaded.
+    pub fn keep_tracking_data(&mut self, previous: &Story) {
+        self.overrides = previous.overrides.clone();
+        self.hashes = previous.hashes.clone();
+        self.added_at = previous.added_at;
+        self.last_checked_at = previous.last_checked_at;
+        self.last_downloaded_at = previous.last_downloaded_at;
+        self.last_download_path = previous.last_download_path.clone();
+        self.tags = previous.tags.clone();
+        self.note = previous.note.clone();
+    }
+
     /// Checks for the existence of an update from the comparison with a more recent version of
     /// [`Story`].
     ///
@@ -144,13 +313,12 @@ mod test {
         datetime: Option<DateTime<Utc>>,
     ) -> Story {
         Story {
-            id: 100001,
             title: "An Active Story".into(),
             author: "A New Author".into(),
             chapter_count: chapters.unwrap_or(5),
             words: words.unwrap_or(12050),
             update_datetime: datetime.unwrap_or_else(|| datetime!(2021, 1, 19, 23, 0, 0)),
-            status: StoryStatus::Incomplete,
+            ..Story::default_for_test(100001)
         }
     }
 
@@ -213,11 +381,106 @@ mod test {
         assert_eq!(story.update_datetime, datetime!(1607137200));
         assert_eq!(story.status, StoryStatus::Complete);
         assert_eq!(story.url(), "https://www.fimfiction.net/story/100000");
+        assert_eq!(story.url_in(&Config::default()), story.url());
 
         let json = serde_json::to_string(&story).expect("couldn't serialize Story into json");
         assert_eq!(json, story_json);
     }
 
+    #[test]
+    fn hashes_serialization() {
+        let mut story = story!();
+        assert!(serde_json::to_value(&story)
+            .unwrap()
+            .get("hashes")
+            .is_none());
+
+        story
+            .hashes
+            .insert(DownloadFormat::TXT, "e3b0c44298fc1c14".into());
+        story
+            .hashes
+            .insert(DownloadFormat::EPUB, "9f86d081884c7d65".into());
+
+        let json = serde_json::to_value(&story).unwrap();
+        assert_eq!(
+            json["hashes"],
+            json!({ "epub": "9f86d081884c7d65", "txt": "e3b0c44298fc1c14" })
+        );
+
+        let deserialized: Story = serde_json::from_value(json).unwrap();
+        assert_eq!(deserialized.hashes, story.hashes);
+    }
+
+    #[test]
+    fn tracking_data_serialization() {
+        let mut story = story!();
+        let json = serde_json::to_value(&story).unwrap();
+        assert!(json.get("added-timestamp").is_none());
+        assert!(json.get("last-download-path").is_none());
+        assert!(json.get("tags").is_none());
+
+        let deserialized: Story = serde_json::from_value(json).unwrap();
+        assert_eq!(deserialized.last_checked_at, None);
+
+        story.added_at = Some(datetime!(2021, 1, 10, 12, 0, 0));
+        story.last_downloaded_at = Some(datetime!(2021, 1, 19, 23, 0, 0));
+        story.last_download_path = Some(PathBuf::from("An Active Story.html"));
+        story.tags.insert("to-read".into());
+        story.tags.insert("sci-fi".into());
+        story.note = Some("Read after the prequel".into());
+
+        let json = serde_json::to_value(&story).unwrap();
+        assert_eq!(json["added-timestamp"], json!(1610280000));
+        assert_eq!(json["last-download-timestamp"], json!(1611097200));
+        assert_eq!(json["last-download-path"], json!("An Active Story.html"));
+        assert!(json.get("last-check-timestamp").is_none());
+        assert_eq!(json["tags"], json!(["sci-fi", "to-read"]));
+        assert_eq!(json["note"], json!("Read after the prequel"));
+
+        let deserialized: Story = serde_json::from_value(json).unwrap();
+        assert_eq!(deserialized.added_at, story.added_at);
+        assert_eq!(deserialized.last_downloaded_at, story.last_downloaded_at);
+        assert_eq!(deserialized.last_download_path, story.last_download_path);
+        assert_eq!(deserialized.tags, story.tags);
+        assert_eq!(deserialized.note, story.note);
+    }
+
+    #[test]
+    fn overrides_serialization() {
+        let mut story = story!();
+        assert!(serde_json::to_value(&story)
+            .unwrap()
+            .get("overrides")
+            .is_none());
+
+        story.overrides = StoryOverrides {
+            download_format: Some(vec![DownloadFormat::EPUB, DownloadFormat::TXT]),
+            sensibility_level: Some(SensibilityLevel::Anything),
+            skip_download: Some(false),
+            ..StoryOverrides::default()
+        };
+
+        let json = serde_json::to_value(&story).unwrap();
+        assert_eq!(
+            json["overrides"],
+            json!({
+                "download-format": ["epub", "txt"],
+                "sensibility-level": 2,
+                "skip-download": false
+            })
+        );
+
+        let deserialized: Story = serde_json::from_value(json).unwrap();
+        assert_eq!(deserialized.overrides, story.overrides);
+
+        let overrides: StoryOverrides =
+            serde_json::from_value(json!({ "download-format": "html", "exec": "" })).unwrap();
+        assert_eq!(overrides.download_format, Some(vec![DownloadFormat::HTML]));
+        assert_eq!(overrides.exec.as_deref(), Some(""));
+        assert!(!overrides.skip_download());
+    }
+
     #[test]
     fn update_comparison() {
         let story = story!();
@@ -231,13 +494,12 @@ mod test {
         assert_no_difference!(story, story!(datetime = datetime!(2021, 1, 10, 12, 0, 0)));
 
         let another_story = Story {
-            id: 100002,
             title: "Not 'An Active Story'".into(),
             author: "Another Author".into(),
             chapter_count: 12,
             words: 14012,
             update_datetime: datetime!(2021, 2, 28, 23, 0, 0),
-            status: StoryStatus::Incomplete,
+            ..Story::default_for_test(100002)
         };
 
         match story.compare_to(&another_story).unwrap_err().kind {
@@ -249,6 +511,31 @@ mod test {
         }
     }
 
+    #[test]
+    fn serialize_update() {
+        let update = StoryUpdate::Chapters {
+            before: 5,
+            after: 6,
+        };
+        assert_eq!(
+            serde_json::to_value(&update).unwrap(),
+            json!({ "on": "chapters", "before": 5, "after": 6 })
+        );
+
+        let update = StoryUpdate::DateTime {
+            before: datetime!(2021, 1, 19, 23, 0, 0),
+            after: datetime!(2021, 2, 14, 23, 0, 0),
+        };
+        assert_eq!(
+            serde_json::to_value(&update).unwrap(),
+            json!({
+                "on": "date-time",
+                "before": "2021-01-19T23:00:00Z",
+                "after": "2021-02-14T23:00:00Z"
+            })
+        );
+    }
+
     #[test]
     fn update_comparison_order() {
         let story = story!();
@@ -1,19 +1,27 @@
 //! Collection of utility functions, structs and traits.
 use std::{
     borrow::Cow,
-    fs, io,
+    fs,
+    io::{self, Write},
     ops::{Deref, DerefMut},
     path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::{Duration, Instant},
 };
 
+use chrono::Local;
 use directories::ProjectDirs;
+use fs2::FileExt;
 use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
 use shellexpand::env_with_context_no_errors;
 
 use url::Url;
 
-use crate::config::{Config, DownloadFormat};
+use crate::config::{Config, DownloadFormat, DEFAULT_FILENAME_TEMPLATE};
 use crate::errors::{self, Action, ErrorKind, TrackerError};
+use crate::migration::{self, Migration, TRACKER_FORMAT_VERSION};
 use crate::story::{Id, Story};
 
 #[cfg(any(target_os = "windows", target_os = "macos"))]
@@ -42,13 +50,17 @@ pub fn default_user_tracker_file() -> PathBuf {
     PROJECT_DIRS.data_local_dir().join("track-data.json")
 }
 
+/// Path to the default directory where the responses from Fimfiction are kept.
+///
+/// The directory corresponds to [`ProjectDirs::cache_dir()`].
+pub fn default_user_cache_dir() -> PathBuf {
+    PROJECT_DIRS.cache_dir().to_path_buf()
+}
+
 /// Creates a Fimfiction story download [`Url`] to the [`Story`] in the given
-/// [`format`](DownloadFormat).
-pub fn download_url_format(story: &Story, format: DownloadFormat) -> Url {
-    Url::parse("https://www.fimfiction.net/story/download/")
-        .and_then(|u| u.join(&format!("{}/", story.id)))
-        .and_then(|u| u.join(format.to_string().as_ref()))
-        .expect("Fimficiton download URL parse failed")
+/// [`format`](DownloadFormat), in `config.base_url`.
+pub fn download_url_format(story: &Story, format: DownloadFormat, config: &Config) -> Url {
+    config.site_url(&format!("story/download/{}/{}", story.id, format))
 }
 
 /// Performs a shell-like environment expansion with [`shellexpand::env_with_context_no_errors()`]
@@ -61,17 +73,34 @@ pub fn download_url_format(story: &Story, format: DownloadFormat) -> Url {
 /// - `CHAPTERS`: The value of `story.chapter_count`.
 /// - `WORDS`: The value of `story.words`.
 /// - `UPDATE_TIMESTAMP`: The value of `story.update_datetime.timestamp()`.
-/// - `URL`: The value of `story.url()`.
+/// - `UPDATE_DATE`: The date of `story.update_datetime`, in the form of `"YYYY-MM-DD"`.
+/// - `UPDATE_YEAR`: The year of `story.update_datetime`.
+/// - `UPDATE_MONTH`: The month of `story.update_datetime`, in the form of `"MM"`.
+/// - `DATE`: The current local date, in the form of `"YYYY-MM-DD"`.
+/// - `URL`: The value of `story.url_in(config)`.
 /// - `DOWNLOAD_URL`: Story download URL, in the form of
-///   `"https://www.fimfiction.net/story/download/{ID}/{FORMAT}"`
+///   `"https://www.fimfiction.net/story/download/{ID}/{FORMAT}"` with the default `base_url`.
 /// - `DOWNLOAD_DIR`: The value of `config.download_dir`.
-/// - `FORMAT`: The value of `config.download_format`.
+/// - `FORMAT`: The value of `config.download_format()`.
+/// - `FORMATS`: The values of `config.download_formats`, separated by commas.
+/// - `TAGS`: The values of `story.tags`, separated by commas.
 ///
 /// Unexpected variables are left as is.
 pub fn env_with_command_context<'a>(
     command: &'a str,
     story: &Story,
     config: &Config,
+) -> Cow<'a, str> {
+    env_with_format_context(command, story, config.download_format(), config)
+}
+
+/// [`env_with_command_context()`] where `FORMAT` and `DOWNLOAD_URL` are from `format` instead of
+/// the main download format.
+fn env_with_format_context<'a>(
+    command: &'a str,
+    story: &Story,
+    format: DownloadFormat,
+    config: &Config,
 ) -> Cow<'a, str> {
     env_with_context_no_errors(command, |var| -> Option<String> {
         match var {
@@ -81,15 +110,65 @@ pub fn env_with_command_context<'a>(
             "CHAPTERS" => Some(story.chapter_count.to_string()),
             "WORDS" => Some(story.words.to_string()),
             "UPDATE_TIMESTAMP" => Some(story.update_datetime.timestamp().to_string()),
-            "URL" => Some(story.url()),
-            "DOWNLOAD_URL" => Some(download_url_format(story, config.download_format).to_string()),
+            "UPDATE_DATE" => Some(story.update_datetime.format("%Y-%m-%d").to_string()),
+            "UPDATE_YEAR" => Some(story.update_datetime.format("%Y").to_string()),
+            "UPDATE_MONTH" => Some(story.update_datetime.format("%m").to_string()),
+            "DATE" => Some(Local::now().format("%Y-%m-%d").to_string()),
+            "URL" => Some(story.url_in(config)),
+            "DOWNLOAD_URL" => Some(download_url_format(story, format, config).to_string()),
             "DOWNLOAD_DIR" => Some(config.download_dir.display().to_string()),
-            "FORMAT" => Some(config.download_format.to_string()),
+            "FORMAT" => Some(format.to_string()),
+            "FORMATS" => Some(
+                config
+                    .download_formats
+                    .iter()
+                    .map(|format| format.to_string())
+                    .collect::<Vec<_>>()
+                    .join(","),
+            ),
+            "TAGS" => Some(
+                story
+                    .tags
+                    .iter()
+                    .map(|tag| tag.as_str())
+                    .collect::<Vec<_>>()
+                    .join(","),
+            ),
             _ => None,
         }
     })
 }
 
+fn template_path(
+    template: &str,
+    story: &Story,
+    format: DownloadFormat,
+    config: &Config,
+) -> PathBuf {
+    env_with_format_context(template, story, format, config)
+        .split('/')
+        .map(sanitize_filename)
+        .filter(|component| !matches!(component.as_str(), "" | "." | ".."))
+        .collect()
+}
+
+/// Path of the file where `story` is downloaded to in `format` when downloading directly from
+/// Fimfiction, from the expansion of `config.filename_template` inside of `config.download_dir`.
+///
+/// The template is expanded with [`env_with_command_context()`], with `FORMAT` being `format`,
+/// and then split by `/` into
+/// subdirectories, each made safe to use as a filename with [`sanitize_filename()`]. Components
+/// that end up empty, `.` or `..` are dropped so the path can't point outside of the download
+/// directory, falling back to `"$TITLE.$FORMAT"` if none is left.
+pub fn download_path(story: &Story, format: DownloadFormat, config: &Config) -> PathBuf {
+    let mut path = template_path(&config.filename_template, story, format, config);
+    if path.as_os_str().is_empty() {
+        path = template_path(DEFAULT_FILENAME_TEMPLATE, story, format, config);
+    }
+
+    config.download_dir.join(path)
+}
+
 /// Replaces forbidden characters present in `filename` with `_`.
 ///
 /// The forbidden characters defined are `>`, `<`, `:`, `"`, `?`, `*`, `/` and `\`.
@@ -116,17 +195,54 @@ where
     })
 }
 
+/// Appends `suffix` to the filename of `path`.
+pub(crate) fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut filename = path
+        .file_name()
+        .map(|name| name.to_os_string())
+        .unwrap_or_default();
+    filename.push(suffix);
+    path.with_file_name(filename)
+}
+
+/// Path to the backup of the file in `path`, the same path with a `.bak` suffix.
+pub(crate) fn backup_path<P>(path: P) -> PathBuf
+where
+    P: AsRef<Path>,
+{
+    with_suffix(path.as_ref(), ".bak")
+}
+
+fn write_error(err: io::Error, path: &Path) -> TrackerError {
+    TrackerError::io(err).context(format!("failed to write into file `{}`", path.display()))
+}
+
+/// Asynchronous version of [`write()`].
 pub async fn async_write<P, C>(path: P, contents: C) -> errors::Result<()>
 where
     P: AsRef<Path>,
     C: AsRef<[u8]>,
 {
-    tokio::fs::write(&path, contents).await.map_err(|err| {
-        TrackerError::io(err).context(format!(
-            "failed to write into file `{}`",
-            path.as_ref().display()
-        ))
-    })
+    let path = path.as_ref();
+    let temp_path = with_suffix(path, ".tmp");
+
+    let result = async {
+        tokio::fs::write(&temp_path, contents).await?;
+        tokio::fs::OpenOptions::new()
+            .write(true)
+            .open(&temp_path)
+            .await?
+            .sync_all()
+            .await?;
+        tokio::fs::rename(&temp_path, path).await
+    }
+    .await;
+
+    if result.is_err() {
+        let _ = tokio::fs::remove_file(&temp_path).await;
+    }
+
+    result.map_err(|err| write_error(err, path))
 }
 
 pub fn read_to_string<P>(path: P) -> errors::Result<String>
@@ -138,24 +254,70 @@ where
     })
 }
 
+/// Writes `contents` into `path` atomically.
+///
+/// The contents are written into a temporary file in the same directory, flushed to disk and
+/// then renamed to `path`. So if anything were to interrupt the writing, `path` is left untouched.
 pub fn write<P, C>(path: P, contents: C) -> errors::Result<()>
 where
     P: AsRef<Path>,
     C: AsRef<[u8]>,
 {
-    fs::write(&path, contents).map_err(|err| {
-        TrackerError::io(err).context(format!(
-            "failed to write into file `{}`",
-            path.as_ref().display()
-        ))
-    })
+    let path = path.as_ref();
+    let temp_path = with_suffix(path, ".tmp");
+
+    let result = (|| {
+        let mut file = fs::File::create(&temp_path)?;
+        file.write_all(contents.as_ref())?;
+        file.sync_all()?;
+        fs::rename(&temp_path, path)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+
+    result.map_err(|err| write_error(err, path))
 }
 
+/// Layout of the track data file, see [`migration`] for the previous ones.
+#[derive(Serialize)]
+struct TrackerFile<'a> {
+    version: u64,
+    stories: Vec<&'a Story>,
+}
+
+#[derive(Deserialize)]
+struct TrackerFileContent {
+    stories: Vec<Story>,
+}
+
+/// How often to try to acquire the lock on the track data file in
+/// [`StoryData::lock_timeout()`].
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
 /// Struct to handle the loading and saving of the track data file.
+///
+/// Every save keeps the previous version of the track data file as a backup, with the same path
+/// and a `.bak` suffix, which is used when loading if the track data file is found to be corrupt.
+///
+/// Track data files with an older version of the format are migrated when loading them, being
+/// written with the current version on the next save. The applied migrations are available in
+/// [`StoryData::migrations()`].
+///
+/// To avoid other processes from overwriting the changes made, an advisory lock is held on the
+/// track data file from [`StoryData::load()`] until [`StoryData`] is dropped or
+/// [`StoryData::unlock()`] is called. The lock is done on a file with the same path and a `.lock`
+/// suffix. When the track data is only going to be read, a shared lock can be acquired with
+/// [`StoryData::lock_shared()`] instead, allowing other processes to read it at the same time.
 #[derive(Debug)]
 pub struct StoryData {
     path: String,
     data: IndexMap<Id, Story>,
+    loaded_from_backup: AtomicBool,
+    migrations: Vec<Migration>,
+    lock: Option<fs::File>,
+    shared_lock: bool,
 }
 
 impl Deref for StoryData {
@@ -181,38 +343,238 @@ impl StoryData {
         StoryData {
             path: path.as_ref().to_string_lossy().into(),
             data: IndexMap::new(),
+            loaded_from_backup: AtomicBool::new(false),
+            migrations: Vec::new(),
+            lock: None,
+            shared_lock: false,
+        }
+    }
+
+    fn lock_error(&self, err: io::Error) -> TrackerError {
+        TrackerError::io(err).context(format!("failed to lock tracker file `{}`", self.path))
+    }
+
+    /// Opens the lock file and tries to acquire an exclusive lock on it, or a shared one if
+    /// `shared`, without blocking, returning `None` if it is held by another process.
+    fn try_acquire_lock(&self, shared: bool) -> errors::Result<Option<fs::File>> {
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(with_suffix(Path::new(&self.path), ".lock"))
+            .map_err(|err| self.lock_error(err))?;
+
+        let result = if shared {
+            FileExt::try_lock_shared(&file)
+        } else {
+            FileExt::try_lock_exclusive(&file)
+        };
+
+        match result {
+            Ok(_) => Ok(Some(file)),
+            Err(err) if err.raw_os_error() == fs2::lock_contended_error().raw_os_error() => {
+                Ok(None)
+            }
+            Err(err) => Err(self.lock_error(err)),
+        }
+    }
+
+    /// Whether the lock on the track data file is being held, be it exclusive or shared.
+    pub fn is_locked(&self) -> bool {
+        self.lock.is_some()
+    }
+
+    /// Whether the lock held on the track data file is a shared one.
+    pub fn is_locked_shared(&self) -> bool {
+        self.lock.is_some() && self.shared_lock
+    }
+
+    fn lock_with(&mut self, shared: bool) -> errors::Result<()> {
+        if self.lock.is_none() {
+            match self.try_acquire_lock(shared)? {
+                Some(file) => {
+                    self.lock = Some(file);
+                    self.shared_lock = shared;
+                }
+                None => return Err(TrackerError::tracker_locked(self.path.clone())),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Acquires the exclusive lock on the track data file if no lock is already held.
+    ///
+    /// # Errors
+    ///
+    /// - If the lock is held by another process, with an error of kind
+    ///   [`TrackerLocked`](ErrorKind::TrackerLocked).
+    /// - If the lock file cannot be opened.
+    pub fn lock(&mut self) -> errors::Result<()> {
+        self.lock_with(false)
+    }
+
+    /// Acquires a shared lock on the track data file if no lock is already held, which other
+    /// processes can hold at the same time to read it, but not the exclusive one.
+    ///
+    /// The track data can't be saved while only holding a shared lock.
+    ///
+    /// # Errors
+    ///
+    /// - If the exclusive lock is held by another process, with an error of kind
+    ///   [`TrackerLocked`](ErrorKind::TrackerLocked).
+    /// - If the lock file cannot be opened.
+    pub fn lock_shared(&mut self) -> errors::Result<()> {
+        self.lock_with(true)
+    }
+
+    fn lock_timeout_with(&mut self, timeout: Option<Duration>, shared: bool) -> errors::Result<()> {
+        let start = Instant::now();
+
+        while self.lock.is_none() {
+            match self.try_acquire_lock(shared)? {
+                Some(file) => {
+                    self.lock = Some(file);
+                    self.shared_lock = shared;
+                }
+                None if matches!(timeout, Some(timeout) if start.elapsed() >= timeout) => {
+                    return Err(
+                        TrackerError::tracker_locked(self.path.clone()).context(format!(
+                            "gave up waiting for the lock after {} seconds",
+                            start.elapsed().as_secs()
+                        )),
+                    );
+                }
+                None => thread::sleep(LOCK_RETRY_INTERVAL),
+            }
         }
+
+        Ok(())
+    }
+
+    /// Acquires the exclusive lock on the track data file if no lock is already held, waiting
+    /// for another process to release it.
+    ///
+    /// If `timeout` is `None` it waits indefinitely.
+    ///
+    /// # Errors
+    ///
+    /// - If the lock is still held by another process after `timeout`, with an error of kind
+    ///   [`TrackerLocked`](ErrorKind::TrackerLocked).
+    /// - If the lock file cannot be opened.
+    pub fn lock_timeout(&mut self, timeout: Option<Duration>) -> errors::Result<()> {
+        self.lock_timeout_with(timeout, false)
+    }
+
+    /// Shared version of [`StoryData::lock_timeout()`], as in [`StoryData::lock_shared()`].
+    pub fn lock_shared_timeout(&mut self, timeout: Option<Duration>) -> errors::Result<()> {
+        self.lock_timeout_with(timeout, true)
     }
 
-    fn load_data_from_string(&mut self, content: String) -> errors::Result<()> {
-        let mut stories: Vec<Story> = serde_json::from_str(&content).map_err(|err| {
-            TrackerError::tracker_format(self.path.clone(), err, Action::Deserializing)
-        })?;
-        self.data = stories
+    /// Releases the lock on the track data file, if held.
+    pub fn unlock(&mut self) {
+        // Closing the file releases the lock.
+        self.lock = None;
+    }
+
+    fn data_from_string(
+        path: &str,
+        content: &str,
+    ) -> errors::Result<(IndexMap<Id, Story>, Vec<Migration>)> {
+        let format_error =
+            |err| TrackerError::tracker_format(path.to_string(), err, Action::Deserializing);
+
+        let value = serde_json::from_str(content).map_err(format_error)?;
+        let (value, migrations) = migration::migrate(path, value)?;
+        let mut content: TrackerFileContent =
+            serde_json::from_value(value).map_err(format_error)?;
+
+        let data = content
+            .stories
             .drain(..)
             .map(|story| (story.id, story))
             .collect::<IndexMap<Id, Story>>();
 
-        Ok(())
+        Ok((data, migrations))
+    }
+
+    fn load_data_from_string(
+        &mut self,
+        content: String,
+        backup: Option<String>,
+    ) -> errors::Result<()> {
+        let mut error = match Self::data_from_string(&self.path, &content) {
+            Ok((data, migrations)) => {
+                self.data = data;
+                self.migrations = migrations;
+                *self.loaded_from_backup.get_mut() = false;
+                return Ok(());
+            }
+            Err(error) => error,
+        };
+
+        if !matches!(error.kind, ErrorKind::TrackerFormat { .. }) {
+            return Err(error);
+        }
+
+        let backup_path = self.backup_path().to_string_lossy().into_owned();
+        match backup.map(|content| Self::data_from_string(&backup_path, &content)) {
+            Some(Ok((data, migrations))) => {
+                warn!(
+                    "{}. Loaded the backup `{}` instead, the next save will overwrite the \
+                    corrupted file",
+                    error, backup_path
+                );
+                self.data = data;
+                self.migrations = migrations;
+                *self.loaded_from_backup.get_mut() = true;
+                Ok(())
+            }
+            Some(Err(_)) => {
+                if let ErrorKind::TrackerFormat { ref mut backup, .. } = error.kind {
+                    let _ = backup.insert(backup_path);
+                }
+                Err(error)
+            }
+            None => Err(error),
+        }
     }
 
     fn data_to_string(&self) -> errors::Result<String> {
-        let stories = self.data.values().collect::<Vec<&Story>>();
-        serde_json::to_string(&stories)
+        let content = TrackerFile {
+            version: TRACKER_FORMAT_VERSION,
+            stories: self.data.values().collect(),
+        };
+
+        serde_json::to_string(&content)
             .map_err(|err| TrackerError::tracker_format(None, err, Action::Serializing))
     }
 
     /// If the track data file exists maps its contents into the cached data, completely
     /// overwriting it. Otherwise, nothing is changed.
     ///
+    /// If the contents of the track data file cannot be deserialized, its backup is loaded
+    /// instead with a warning.
+    ///
+    /// Acquires the exclusive lock on the track data file, without waiting for it, if no lock is
+    /// already held. Use [`StoryData::lock_timeout()`] beforehand to wait for another process to
+    /// release it, or [`StoryData::lock_shared()`] to only read the track data.
+    ///
     /// # Errors
     ///
+    /// - According to [`StoryData::lock()`].
     /// - If [`std::fs::read_to_string()`] returns a no [`NotFound`](io::ErrorKind::NotFound)
-    /// error.
-    /// - On deserialization errors with the contents of the track data file.
+    ///   error.
+    /// - On deserialization errors with the contents of the track data file, when there is no
+    ///   valid backup to fall back to.
     pub fn load(&mut self) -> errors::Result<()> {
+        self.lock()?;
+
         match read_to_string(&self.path) {
-            Ok(content) => self.load_data_from_string(content),
+            Ok(content) => {
+                let backup = fs::read_to_string(self.backup_path()).ok();
+                self.load_data_from_string(content, backup)
+            }
             Err(TrackerError {
                 kind: ErrorKind::Io(err),
                 ..
@@ -223,8 +585,13 @@ impl StoryData {
 
     /// Asynchronous version of [`StoryData::load()`].
     pub async fn async_load(&mut self) -> errors::Result<()> {
+        self.lock()?;
+
         match async_read_to_string(&self.path).await {
-            Ok(content) => self.load_data_from_string(content),
+            Ok(content) => {
+                let backup = tokio::fs::read_to_string(self.backup_path()).await.ok();
+                self.load_data_from_string(content, backup)
+            }
             Err(TrackerError {
                 kind: ErrorKind::Io(err),
                 ..
@@ -233,17 +600,97 @@ impl StoryData {
         }
     }
 
-    /// Takes the cached track data and writes it into the track data file.
+    /// Whether the cached data was loaded from the backup of the track data file, and hasn't been
+    /// saved since.
+    pub fn loaded_from_backup(&self) -> bool {
+        self.loaded_from_backup.load(Ordering::Relaxed)
+    }
+
+    /// The migrations applied to the track data file on the last load, in order.
+    ///
+    /// Empty if it was already in the current version of the format.
+    pub fn migrations(&self) -> &[Migration] {
+        &self.migrations
+    }
+
+    /// Path to the track data file.
+    pub fn path(&self) -> &Path {
+        Path::new(&self.path)
+    }
+
+    /// Path to the backup of the track data file.
+    pub fn backup_path(&self) -> PathBuf {
+        backup_path(&self.path)
+    }
+
+    /// Acquires the exclusive lock for the duration of a save if no lock is already held.
+    fn save_lock(&self) -> errors::Result<Option<fs::File>> {
+        if self.is_locked_shared() {
+            return Err(TrackerError::tracker_locked(self.path.clone())
+                .context("only a shared lock is held, which doesn't allow saving"));
+        } else if self.lock.is_some() {
+            return Ok(None);
+        }
+
+        match self.try_acquire_lock(false)? {
+            Some(file) => Ok(Some(file)),
+            None => Err(TrackerError::tracker_locked(self.path.clone())),
+        }
+    }
+
+    fn backup_error(&self, err: io::Error) -> TrackerError {
+        TrackerError::io(err).context(format!(
+            "failed to backup file `{}` before saving",
+            self.path
+        ))
+    }
+
+    /// Takes the cached track data and atomically writes it into the track data file, keeping
+    /// its previous version as a backup.
+    ///
+    /// The backup is written atomically too, and left untouched if the cached data was loaded from
+    /// it since the last save, since it means that the track data file was corrupt.
+    ///
+    /// If the lock on the track data file isn't held, it is acquired for the duration of the
+    /// save.
+    ///
+    /// # Errors
+    ///
+    /// - If the lock isn't held and another process holds it, or only a shared lock is held, with
+    ///   an error of kind [`TrackerLocked`](ErrorKind::TrackerLocked).
+    /// - If the track data file or its backup cannot be written.
     pub fn save(&self) -> errors::Result<()> {
         let data = self.data_to_string()?;
+        let _lock = self.save_lock()?;
+
+        if !self.loaded_from_backup() {
+            match fs::read(&self.path) {
+                Ok(previous) => write(self.backup_path(), previous)?,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => return Err(self.backup_error(err)),
+            }
+        }
+
         write(&self.path, data)?;
+        self.loaded_from_backup.store(false, Ordering::Relaxed);
         Ok(())
     }
 
     /// Asynchronous version of [`StoryData::save()`].
     pub async fn async_save(&self) -> errors::Result<()> {
         let data = self.data_to_string()?;
+        let _lock = self.save_lock()?;
+
+        if !self.loaded_from_backup() {
+            match tokio::fs::read(&self.path).await {
+                Ok(previous) => async_write(self.backup_path(), previous).await?,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => return Err(self.backup_error(err)),
+            }
+        }
+
         async_write(&self.path, data).await?;
+        self.loaded_from_backup.store(false, Ordering::Relaxed);
         Ok(())
     }
 }
@@ -253,19 +700,17 @@ mod test {
     use super::*;
 
     use chrono::Utc;
+    use tempdir::TempDir;
+
+    use crate::config::ConfigBuilder;
 
     #[test]
     fn download_url_builder() {
         use crate::{config::DownloadFormat, StoryStatus};
 
         let story = Story {
-            id: 165,
-            title: "A Title".into(),
-            author: "An Author".into(),
-            chapter_count: 5,
-            words: 15017,
-            update_datetime: Utc::now(),
             status: StoryStatus::Complete,
+            ..Story::default_for_test(165)
         };
 
         macro_rules! assert_formats {
@@ -276,12 +721,254 @@ mod test {
                             "https://www.fimfiction.net/story/download/{}/{}",
                             story.id, DownloadFormat::$kind
                         ),
-                        download_url_format(&story, DownloadFormat::$kind).as_str()
+                        download_url_format(&story, DownloadFormat::$kind, &Config::default()).as_str()
                     );
                 )+
             }
         }
 
         assert_formats!(HTML, EPUB, TXT);
+
+        let config: Config = ConfigBuilder::new()
+            .base_url("http://localhost:8080/fimfiction".parse().unwrap())
+            .into();
+        assert_eq!(
+            download_url_format(&story, DownloadFormat::EPUB, &config).as_str(),
+            "http://localhost:8080/fimfiction/story/download/165/epub"
+        );
+    }
+
+    #[test]
+    fn download_path_from_template() {
+        use chrono::TimeZone;
+
+        let mut story = Story {
+            title: "A Title: The Sequel".into(),
+            author: "An/Author".into(),
+            update_datetime: Utc.with_ymd_and_hms(2021, 2, 14, 23, 0, 0).unwrap(),
+            status: crate::StoryStatus::Complete,
+            ..Story::default_for_test(165)
+        };
+
+        macro_rules! assert_path {
+            ($template:expr => $path:expr) => {
+                let config: Config = ConfigBuilder::new()
+                    .download_dir("/downloads")
+                    .filename_template($template)
+                    .into();
+                assert_eq!(
+                    download_path(&story, DownloadFormat::HTML, &config),
+                    Path::new($path)
+                );
+            };
+        }
+
+        assert_path!("$TITLE.$FORMAT" => "/downloads/A Title_ The Sequel.html");
+        assert_path!(
+            "$AUTHOR/$TITLE ($ID).$FORMAT" => "/downloads/An_Author/A Title_ The Sequel (165).html"
+        );
+        assert_path!("$UPDATE_YEAR/$UPDATE_MONTH/$ID.$FORMAT" => "/downloads/2021/02/165.html");
+        assert_path!("$UPDATE_DATE $ID.txt" => "/downloads/2021-02-14 165.txt");
+        assert_path!("/../$AUTHOR//./$ID" => "/downloads/An_Author/165");
+        assert_path!("../" => "/downloads/A Title_ The Sequel.html");
+
+        let config: Config = ConfigBuilder::new()
+            .download_dir("/downloads")
+            .download_formats([DownloadFormat::EPUB, DownloadFormat::TXT])
+            .filename_template("$FORMAT/$ID.$FORMAT")
+            .into();
+        assert_eq!(
+            download_path(&story, DownloadFormat::TXT, &config),
+            Path::new("/downloads/txt/165.txt")
+        );
+        assert_eq!(
+            env_with_command_context("$FORMAT $FORMATS $DOWNLOAD_URL", &story, &config),
+            "epub epub,txt https://www.fimfiction.net/story/download/165/epub"
+        );
+
+        assert_eq!(env_with_command_context("[$TAGS]", &story, &config), "[]");
+        story.tags.insert("to-read".into());
+        story.tags.insert("sci-fi".into());
+        assert_eq!(
+            env_with_command_context("$TAGS", &story, &config),
+            "sci-fi,to-read"
+        );
+    }
+
+    #[test]
+    fn save_keeps_backup() {
+        let tmp_dir = TempDir::new("fft-save-backup").expect("failed to create temp dir");
+        let path = tmp_dir.path().join("track-data.json");
+
+        let mut story_data = StoryData::new(&path);
+        story_data.insert(1, Story::default_for_test(1));
+        story_data.save().expect("failed to save story data");
+        assert!(!backup_path(&path).exists());
+
+        story_data.insert(2, Story::default_for_test(2));
+        story_data.save().expect("failed to save story data");
+        assert!(!with_suffix(&path, ".tmp").exists());
+
+        let mut backup = StoryData::new(backup_path(&path));
+        backup.load().expect("failed to load backup");
+        assert_eq!(backup.keys().collect::<Vec<_>>(), [&1]);
+
+        let mut story_data = StoryData::new(&path);
+        story_data.load().expect("failed to load story data");
+        assert_eq!(story_data.keys().collect::<Vec<_>>(), [&1, &2]);
+        assert!(!story_data.loaded_from_backup());
+    }
+
+    #[test]
+    fn save_after_loading_backup() {
+        let tmp_dir = TempDir::new("fft-save-backup").expect("failed to create temp dir");
+        let path = tmp_dir.path().join("track-data.json");
+        let keys = |path: &Path| {
+            let mut story_data = StoryData::new(path);
+            story_data.load().expect("failed to load story data");
+            story_data.keys().cloned().collect::<Vec<_>>()
+        };
+
+        let mut story_data = StoryData::new(&path);
+        story_data.insert(1, Story::default_for_test(1));
+        story_data.save().expect("failed to save story data");
+        story_data.save().expect("failed to save story data");
+        drop(story_data);
+        fs::write(&path, "{").expect("failed to corrupt tracker file");
+
+        let mut story_data = StoryData::new(&path);
+        story_data.load().expect("should load the backup");
+        story_data.insert(2, Story::default_for_test(2));
+        story_data.save().expect("failed to save story data");
+        assert!(!story_data.loaded_from_backup());
+        assert!(!with_suffix(&backup_path(&path), ".tmp").exists());
+
+        // The corrupt file isn't kept as the backup, but the next save backs up the fixed one.
+        story_data.insert(3, Story::default_for_test(3));
+        story_data.unlock();
+        assert_eq!(keys(&backup_path(&path)), [1]);
+        story_data.save().expect("failed to save story data");
+        assert_eq!(keys(&backup_path(&path)), [1, 2]);
+        assert_eq!(keys(&path), [1, 2, 3]);
+    }
+
+    #[test]
+    fn lock_is_exclusive() {
+        let tmp_dir = TempDir::new("fft-lock").expect("failed to create temp dir");
+        let path = tmp_dir.path().join("track-data.json");
+
+        let mut story_data = StoryData::new(&path);
+        story_data.load().expect("failed to load story data");
+        assert!(story_data.is_locked());
+
+        let mut other = StoryData::new(&path);
+        assert!(matches!(
+            other.load().unwrap_err().kind,
+            ErrorKind::TrackerLocked { .. }
+        ));
+        assert!(matches!(
+            other.save().unwrap_err().kind,
+            ErrorKind::TrackerLocked { .. }
+        ));
+        assert!(matches!(
+            other
+                .lock_timeout(Some(Duration::from_millis(250)))
+                .unwrap_err()
+                .kind,
+            ErrorKind::TrackerLocked { .. }
+        ));
+
+        story_data.insert(1, Story::default_for_test(1));
+        story_data
+            .save()
+            .expect("failed to save while holding the lock");
+        story_data.unlock();
+
+        other
+            .lock_timeout(Some(Duration::from_millis(250)))
+            .expect("lock should have been released");
+        other.load().expect("failed to load story data");
+        assert_eq!(other.keys().collect::<Vec<_>>(), [&1]);
+    }
+
+    #[test]
+    fn lock_can_be_shared() {
+        let tmp_dir = TempDir::new("fft-lock").expect("failed to create temp dir");
+        let path = tmp_dir.path().join("track-data.json");
+
+        let mut reader = StoryData::new(&path);
+        reader.lock_shared().expect("failed to lock story data");
+        reader.load().expect("failed to load story data");
+        assert!(reader.is_locked_shared());
+
+        let mut other_reader = StoryData::new(&path);
+        other_reader
+            .lock_shared_timeout(Some(Duration::from_millis(250)))
+            .expect("shared locks should be held at the same time");
+        other_reader.load().expect("failed to load story data");
+
+        let mut writer = StoryData::new(&path);
+        assert!(matches!(
+            writer.load().unwrap_err().kind,
+            ErrorKind::TrackerLocked { .. }
+        ));
+        assert!(matches!(
+            reader.save().unwrap_err().kind,
+            ErrorKind::TrackerLocked { .. }
+        ));
+
+        reader.unlock();
+        other_reader.unlock();
+        writer.load().expect("failed to load story data");
+        assert!(writer.is_locked() && !writer.is_locked_shared());
+        assert!(matches!(
+            reader.lock_shared().unwrap_err().kind,
+            ErrorKind::TrackerLocked { .. }
+        ));
+    }
+
+    #[test]
+    fn load_falls_back_to_backup() {
+        let tmp_dir = TempDir::new("fft-load-backup").expect("failed to create temp dir");
+        let path = tmp_dir.path().join("track-data.json");
+
+        let mut story_data = StoryData::new(&path);
+        story_data.insert(1, Story::default_for_test(1));
+        story_data.save().expect("failed to save story data");
+        story_data.save().expect("failed to save story data");
+
+        // Simulating an interrupted write.
+        fs::write(&path, "[{\"id\": 1, \"tit").expect("failed to truncate tracker file");
+
+        let mut story_data = StoryData::new(&path);
+        story_data.load().expect("should load the backup");
+        assert!(story_data.loaded_from_backup());
+        assert_eq!(story_data.keys().collect::<Vec<_>>(), [&1]);
+        drop(story_data);
+
+        fs::write(backup_path(&path), "{").expect("failed to corrupt backup");
+
+        let mut story_data = StoryData::new(&path);
+        match story_data.load().unwrap_err().kind {
+            ErrorKind::TrackerFormat {
+                action: Action::Deserializing,
+                backup: Some(backup),
+                ..
+            } => assert_eq!(Path::new(&backup), backup_path(&path)),
+            _ => unreachable!(),
+        }
+        drop(story_data);
+
+        fs::remove_file(backup_path(&path)).expect("failed to remove backup");
+
+        let mut story_data = StoryData::new(&path);
+        match story_data.load().unwrap_err().kind {
+            ErrorKind::TrackerFormat {
+                action: Action::Deserializing,
+                backup: None,
+                ..
+            } => {}
+            _ => unreachable!(),
+        }
     }
 }
@@ -1,20 +1,183 @@
-// TODO: Example or test?
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+use std::thread;
+
+use chrono::Utc;
 use tempdir::TempDir;
+use url::Url;
 
-use fimfic_tracker::{downloader::ProgressListener, Config, ConfigBuilder, Id, Story};
+use fimfic_tracker::{
+    downloader::{DownloadProgress, Downloaded, ProgressListener},
+    errors::ErrorKind,
+    Config, ConfigBuilder, DownloadFormat, Id, Story,
+};
 
-// "The Moon's Apprentice" by Forthwith
 static STORY_ID: Id = 196256;
 
+static STORY_RESPONSE: &str = r#"{
+    "story": {
+        "id": 196256,
+        "title": "The Moon's Apprentice",
+        "url": "https://www.fimfiction.net/story/196256/the-moons-apprentice",
+        "date_modified": 1614556800,
+        "words": 15017,
+        "chapter_count": 5,
+        "status": "Incomplete",
+        "author": { "id": "12345", "name": "Forthwith" }
+    }
+}"#;
+
+/// Value of the `ETag` header of every response of [`MockServer`].
+static ETAG: &str = "\"v1\"";
+
+/// A stand-in for Fimfiction, answering in a background thread with the data of [`STORY_ID`] and
+/// its downloads, whose content is the name of the format.
+///
+/// Every response has an [`ETAG`], answering with `304 Not Modified` to the requests that already
+/// have it.
+struct MockServer {
+    base_url: Url,
+    requests: Arc<AtomicUsize>,
+    not_modified: Arc<AtomicUsize>,
+}
+
+impl MockServer {
+    fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind mock server");
+        let port = listener
+            .local_addr()
+            .expect("mock server should have an address")
+            .port();
+
+        let requests = Arc::new(AtomicUsize::new(0));
+        let not_modified = Arc::new(AtomicUsize::new(0));
+        let (requests_counter, not_modified_counter) =
+            (Arc::clone(&requests), Arc::clone(&not_modified));
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                requests_counter.fetch_add(1, Ordering::SeqCst);
+                if respond(stream) {
+                    not_modified_counter.fetch_add(1, Ordering::SeqCst);
+                }
+            }
+        });
+
+        MockServer {
+            base_url: format!("http://127.0.0.1:{}/fimfiction", port)
+                .parse()
+                .expect("mock server URL should be valid"),
+            requests,
+            not_modified,
+        }
+    }
+
+    /// The amount of requests received, each one in its own connection.
+    fn requests(&self) -> usize {
+        self.requests.load(Ordering::SeqCst)
+    }
+
+    /// The amount of requests answered with `304 Not Modified`.
+    fn not_modified(&self) -> usize {
+        self.not_modified.load(Ordering::SeqCst)
+    }
+
+    /// Configuration to download into `dir/downloads`, keeping the responses in `dir/cache` if
+    /// `cache` is enabled.
+    fn config(&self, dir: &Path, cache: bool) -> Config {
+        ConfigBuilder::new()
+            .base_url(self.base_url.clone())
+            .download_dir(dir.join("downloads").to_string_lossy())
+            .download_formats([DownloadFormat::HTML, DownloadFormat::EPUB])
+            .max_attempts(1)
+            .cache(cache)
+            .cache_dir(dir.join("cache").to_string_lossy())
+            .into()
+    }
+}
+
+/// Answers the request in `stream`, returning whether it was with `304 Not Modified`.
+fn respond(mut stream: TcpStream) -> bool {
+    let mut reader = BufReader::new(stream.try_clone().expect("failed to clone stream"));
+
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line).is_err() {
+        return false;
+    }
+
+    // Only the headers matter, the requests never have a body.
+    let mut if_none_match = None;
+    let mut line = String::new();
+    while reader
+        .read_line(&mut line)
+        .map(|read| read > 2)
+        .unwrap_or(false)
+    {
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("if-none-match") {
+                if_none_match = Some(value.trim().to_string());
+            }
+        }
+        line.clear();
+    }
+
+    let path = request_line.split_whitespace().nth(1).unwrap_or("/");
+    let story_path = format!("/fimfiction/api/story.php?story={}", STORY_ID);
+    let download_path = format!("/fimfiction/story/download/{}/", STORY_ID);
+
+    let (status, body) = if if_none_match.as_deref() == Some(ETAG) {
+        ("304 Not Modified", String::new())
+    } else if path == story_path {
+        ("200 OK", STORY_RESPONSE.to_string())
+    } else if path.starts_with("/fimfiction/api/story.php") {
+        ("200 OK", r#"{"error":"Invalid story id"}"#.to_string())
+    } else if let Some(format) = path.strip_prefix(&download_path) {
+        ("200 OK", format.to_string())
+    } else {
+        ("404 Not Found", String::new())
+    };
+
+    let _ = write!(
+        stream,
+        "HTTP/1.1 {}\r\nETag: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        ETAG,
+        body.len(),
+        body
+    );
+
+    status.starts_with("304")
+}
+
 struct SimpleListener;
 
 impl ProgressListener for SimpleListener {
-    fn download_progress(&self, bytes: usize, filepath: &str) {
+    fn download_progress(&self, progress: &DownloadProgress) {
         println!(
-            "[Download] {} ({}) (started? {})",
-            filepath,
-            bytes,
-            bytes == 0
+            "[Download] {} ({}/{:?}) (started? {})",
+            progress.filepath,
+            progress.bytes,
+            progress.total,
+            progress.is_started()
+        );
+    }
+
+    fn successfull_format_download(&self, story: &Story, format: DownloadFormat, filepath: &Path) {
+        println!(
+            "Download of `{}` in {} finished into {:?}",
+            &story.title, format, filepath
+        );
+    }
+
+    fn unchanged_format_download(&self, story: &Story, format: DownloadFormat, filepath: &Path) {
+        println!(
+            "Download of `{}` in {} is unchanged in {:?}",
+            &story.title, format, filepath
         );
     }
 
@@ -26,50 +189,186 @@ impl ProgressListener for SimpleListener {
     fn successfull_command_execution(&self, _story: &Story) {}
 }
 
+fn assert_story(story: &Story) {
+    assert_eq!(story.id, STORY_ID);
+    assert_eq!(story.title, "The Moon's Apprentice");
+    assert_eq!(story.author, "Forthwith");
+    assert_eq!(story.chapter_count, 5);
+    assert_eq!(story.words, 15017);
+}
+
+fn assert_downloads(downloaded: &Downloaded, download_dir: &Path, unchanged: bool) {
+    let files = match downloaded {
+        Downloaded::Files(files) => files,
+        Downloaded::Command(_) => unreachable!(),
+    };
+
+    assert_eq!(files.len(), 2);
+    for (file, format) in files.iter().zip(["html", "epub"]) {
+        assert_eq!(file.format.to_string(), format);
+        assert_eq!(
+            file.path,
+            download_dir.join(format!("The Moon's Apprentice.{}", format))
+        );
+        assert_eq!(fs::read_to_string(&file.path).unwrap(), format);
+        assert_eq!(file.unchanged, unchanged);
+    }
+
+    assert_eq!(fs::read_dir(download_dir).unwrap().count(), 2);
+}
+
 #[test]
-#[ignore]
 fn test_blocking_download() {
     use fimfic_tracker::downloader::BlockingRequester as Requester;
 
+    let server = MockServer::start();
     let tmp_dir = TempDir::new("fft-blocking-download").expect("failed to create temp dir");
-    let config: Config = ConfigBuilder::new()
-        .download_dir(tmp_dir.path().to_string_lossy())
+    let download_dir = tmp_dir.path().join("downloads");
+    let requester = Requester::new(server.config(tmp_dir.path(), true), SimpleListener)
+        .expect("failed to build requester");
+
+    let mut story: Story = requester
+        .get_story_response(STORY_ID)
+        .expect("failed to request story response")
         .into();
+    assert_story(&story);
+
+    let downloaded = requester
+        .download(&story)
+        .expect("failed to download story with client");
+    assert_downloads(&downloaded, &download_dir, false);
+    assert_eq!(server.not_modified(), 0);
 
-    let requester = Requester::new(config, SimpleListener);
+    // The kept responses are sent back when Fimfiction says that they didn't change.
+    let downloaded_at = Utc::now();
+    downloaded.record_download(&mut story, downloaded_at);
+    assert_eq!(story.last_downloaded_at, Some(downloaded_at));
+    assert_eq!(
+        story.last_download_path,
+        Some(download_dir.join("The Moon's Apprentice.html"))
+    );
+    let downloaded = requester
+        .download(&story)
+        .expect("failed to download story again with client");
+    assert_downloads(&downloaded, &download_dir, true);
+    assert_eq!(server.not_modified(), 2);
+
+    fs::remove_dir_all(&download_dir).unwrap();
+    let downloaded = requester
+        .download(&story)
+        .expect("failed to restore story from cache");
+    assert_downloads(&downloaded, &download_dir, false);
+    assert_eq!(server.not_modified(), 4);
 
     let story: Story = requester
         .get_story_response(STORY_ID)
-        .expect("failed to request story response")
+        .expect("failed to request story response again")
         .into();
-    println!("{:?}", story);
+    assert_story(&story);
+    assert_eq!(server.not_modified(), 5);
 
-    requester
-        .client_download(&story)
+    assert!(requester.get_story_response(1).is_err());
+}
+
+#[test]
+fn test_offline() {
+    use fimfic_tracker::downloader::BlockingRequester as Requester;
+
+    let server = MockServer::start();
+    let tmp_dir = TempDir::new("fft-offline").expect("failed to create temp dir");
+    let download_dir = tmp_dir.path().join("downloads");
+    let mut config = server.config(tmp_dir.path(), true);
+    config.offline = true;
+    let offline =
+        Requester::new(config.clone(), SimpleListener).expect("failed to build requester");
+
+    let err = offline
+        .get_story_response(STORY_ID)
+        .expect_err("nothing should be kept in the cache");
+    assert!(matches!(err.kind, ErrorKind::Offline { .. }));
+    assert_eq!(server.requests(), 0);
+
+    config.offline = false;
+    let online = Requester::new(config, SimpleListener).expect("failed to build requester");
+    let story: Story = online
+        .get_story_response(STORY_ID)
+        .expect("failed to request story response")
+        .into();
+    online
+        .download(&story)
         .expect("failed to download story with client");
+    fs::remove_dir_all(&download_dir).unwrap();
+    let requests = server.requests();
+    assert!(requests > 0);
+
+    // Everything is answered from the cache, without a single request.
+    let story: Story = offline
+        .get_story_response(STORY_ID)
+        .expect("story response should be kept in the cache")
+        .into();
+    assert_story(&story);
+
+    let downloaded = offline
+        .download(&story)
+        .expect("downloads should be kept in the cache");
+    assert_downloads(&downloaded, &download_dir, false);
+
+    let err = offline
+        .get_story_response(1)
+        .expect_err("story 1 shouldn't be kept in the cache");
+    assert!(matches!(err.kind, ErrorKind::Offline { .. }));
+    assert_eq!(server.requests(), requests);
+
+    // Download commands aren't executed at all.
+    let marker = tmp_dir.path().join("executed");
+    let command = format!("touch {}", marker.display());
+    let err = offline
+        .exec_download(&command, &story)
+        .expect_err("commands shouldn't be executed while offline");
+    assert!(matches!(err.kind, ErrorKind::Offline { .. }));
+
+    let mut story = story;
+    story.overrides.exec = Some(command);
+    let err = offline
+        .download(&story)
+        .expect_err("commands shouldn't be executed while offline");
+    assert!(matches!(err.kind, ErrorKind::Offline { .. }));
+    assert!(!marker.exists());
 }
 
 #[tokio::test]
-#[ignore]
 async fn test_sync_download() {
     use fimfic_tracker::downloader::AsyncRequester as Requester;
 
+    let server = MockServer::start();
     let tmp_dir = TempDir::new("fft-sync-download").expect("failed to create temp dir");
-    let config: Config = ConfigBuilder::new()
-        .download_dir(tmp_dir.path().to_string_lossy())
-        .into();
+    let download_dir = tmp_dir.path().join("downloads");
+    let requester = Requester::new(server.config(tmp_dir.path(), false), SimpleListener)
+        .expect("failed to build requester");
 
-    let requester = Requester::new(config, SimpleListener);
-
-    let story: Story = requester
+    let mut story: Story = requester
         .get_story_response(STORY_ID)
         .await
         .expect("failed to request story response")
         .into();
-    println!("{:?}", story);
+    assert_story(&story);
 
-    requester
-        .client_download(&story)
+    let downloaded = requester
+        .download(&story)
         .await
         .expect("failed to download story with client");
+    assert_downloads(&downloaded, &download_dir, false);
+
+    downloaded.record_hashes(&mut story);
+    let downloaded = requester
+        .download(&story)
+        .await
+        .expect("failed to download story again with client");
+    assert_downloads(&downloaded, &download_dir, true);
+
+    // Without the cache, every request transfers the whole response.
+    assert_eq!(server.not_modified(), 0);
+    assert!(!tmp_dir.path().join("cache").exists());
+
+    assert!(requester.get_story_response(1).await.is_err());
 }